@@ -1,5 +1,19 @@
 pub fn main() {
-    engine::run();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let result = match args.first().map(|command| command.as_str()) {
+        Some("render") => snapshot::render(&args[1..]),
+        Some("geojson") => geojson::export(&args[1..]),
+        Some("osm") => osm_xml::export(&args[1..]),
+        Some("apply-osc") => osm_change::run(&args[1..]),
+        Some("stats") => stats::run(&args[1..]),
+        _ => engine::run(&args),
+    };
+
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
 }
 
 pub mod engine {
@@ -8,7 +22,7 @@ pub mod engine {
     use std::time::Duration;
 
     use sdl2::event::Event;
-    use sdl2::keyboard::Keycode;
+    use sdl2::keyboard::{Keycode, Mod};
     use sdl2::pixels::Color;
     use sdl2::rect::Rect;
     use sdl2::render::WindowCanvas;
@@ -18,163 +32,597 @@ pub mod engine {
 
     use crate::random::random_int;
     use super::sprite::*;
-    use crate::osm::{Node, Way, Relation, OutputFormat};
-
+    use crate::basemap::{self, Basemap};
+    use crate::cli::Options;
+    use crate::osm::GeometryMode;
+    use crate::camera::Camera;
+    use crate::ecs::{Ai, Collider, Entity, FollowTrack, Health, Pickup, PlayerControl, Prefabs, Render, Schedule, Transform, World};
+    use crate::geo::LatLon;
+    use crate::geofence::{self, Crossing, Geofence, Locator};
+    use crate::gpx::{self, draw_gpx, Playback};
+    use crate::lod::LevelOfDetail;
+    use crate::map_layer::MapLayer;
+    use crate::minimap::Minimap;
+    use crate::osm::TagFilter;
+    use crate::overlay::Overlay;
+    use crate::search::{SearchBox, SearchIndex};
+    use crate::snapshot;
+    use crate::source;
+    use crate::spawn;
+    use crate::style::StyleSheet;
+    use crate::tiles::TileSource;
+    use crate::timing::{FixedTimestep, GameClock};
+
+    /// game updates per second unless `--tick-rate` says otherwise
+    pub const DEFAULT_TICK_RATE: f64 = 60.0;
+    /// frame cap without `--vsync`, overridden by `--max-fps`
+    pub const DEFAULT_MAX_FPS: f64 = 120.0;
+    /// fetched from overpass when no other source is given
+    pub const DEFAULT_BBOX: &str = "43.731,7.418,43.732,7.419";
+
+    /// the map comes from the same sources as the other commands, see `source::load`, and `--area` masks everything outside the boundary;
+    /// `[--tiles tiles/|tiles.mbtiles]` draws png tiles under the map,
+    /// `[--gpx track.gpx [--time-scale 10]]` draws a gpx file and plays back its first track or route,
+    /// `[--zones leisure=park,amenity=school] [--zone-file zones.geojson]` logs sprites entering and leaving those areas,
+    /// `[--spawn-rules spawn.json]` places enemies and collectibles at matching nodes, see `spawn::SpawnRule`,
+    /// `[--style style.json]` replaces the built in style sheet and is read again on `f5`,
+    /// `[--tick-rate 60] [--vsync | --max-fps 120]` sets how often the game updates and how often it draws
     #[tokio::main]
-    pub async fn run () -> Result<(), Box<dyn Error>> {
-        let mut sdl_components = SdlComponents::init();
-
-        let mut past = sdl_components.timer_subsystem.ticks64();
-        let mut now;
-        let mut past_fps = past;
+    pub async fn run (args: &[String]) -> Result<(), Box<dyn Error>> {
+        let mut options = Options::parse(args)?;
+        if !["file", "mvt", "area", "bbox"].iter().any(|key| options.flag(key)) {
+            options.set_default("bbox", DEFAULT_BBOX);
+        }
+        let tile_source = options.get("tiles").map(|path| TileSource::open(path, basemap::EXTENSIONS)).transpose()?;
+        let gpx = options.get("gpx").map(gpx::read_file).transpose()?;
+        let time_scale = options.get("time-scale").map(|scale| scale.parse()).transpose()?.unwrap_or(1.0);
+        let tick_rate: f64 = options.get("tick-rate").map(|rate| rate.parse()).transpose()?.unwrap_or(DEFAULT_TICK_RATE);
+        let vsync = options.flag("vsync");
+        let max_fps: f64 = options.get("max-fps").map(|fps| fps.parse()).transpose()?.unwrap_or(DEFAULT_MAX_FPS);
+
+        let mut sdl_components = SdlComponents::init(vsync);
+
+        let mut timestep = FixedTimestep::new(tick_rate, sdl_components.timer_subsystem.ticks64());
+        let mut clock = GameClock::default();
+        let mut past_fps = sdl_components.timer_subsystem.ticks64();
         let mut fps = 0;
-        let mut frames_skipped = 0;
 
         let mut input = Input::new();
 
-        let sprites: &mut Vec<Sprite> = &mut Vec::new();
-        add_sprite(sprites, SpriteType::PLAYER, &mut sdl_components);
-        let num_enemies = 10;
-        for _ in 1..=num_enemies {
-            add_sprite(sprites, SpriteType::ENEMY, &mut sdl_components);
-        }
-
-        let nodes = match Node::get_nodes(OutputFormat::XML, 43.731, 7.418, 43.732, 7.419).await {
-            Ok(nodes) => {
-                nodes
-            }
+        let (dataset, boundary) = match source::load(&options, GeometryMode::Geom).await {
+            Ok(loaded) => loaded,
             Err(err) => {
                 eprintln!("Error: {:?}", err);
                 return Err(err);
             }
         };
-
-        let ways = match Way::get_nodes(OutputFormat::XML, 43.731, 7.418, 43.732, 7.419).await {
-            Ok(ways) => {
-                ways
-            }
-            Err(err) => {
-                eprintln!("Error: {:?}", err);
-                return Err(err);
-            }
+        println!("hi");
+        let bbox = match (&boundary, options.get("bbox")) {
+            (Some(boundary), _) => boundary.bbox,
+            (None, Some(_)) => options.bbox("bbox")?,
+            (None, None) => dataset.bbox().ok_or("nothing to show")?,
         };
 
-        let relations = match Relation::get_nodes(OutputFormat::XML, 43.731, 7.418, 43.732, 7.419).await {
-            Ok(relations) => {
-                relations
-            }
-            Err(err) => {
-                eprintln!("Error: {:?}", err);
-                return Err(err);
-            }
+        let (window_width, window_height) = sdl_components.canvas.output_size()?;
+        let mut camera = Camera::fit_bbox(&bbox, window_width, window_height);
+        let style_path = options.get("style");
+        let mut style_sheet = match style_path {
+            Some(path) => StyleSheet::read(path)?,
+            None => StyleSheet::default(),
         };
-        println!("hi");
+        let search_index = SearchIndex::build(&dataset);
+        let lod = LevelOfDetail::build(&dataset);
+        let mut search_box = SearchBox::new();
+        let mut overlay = Overlay::default();
+        let mut minimap = Minimap::new(&dataset, &dataset.bbox().unwrap_or(bbox), window_width, window_height);
+        let texture_creator = sdl_components.canvas.texture_creator();
+        let mut basemap = tile_source.map(Basemap::new);
+        let mut map_layer = MapLayer::default();
+
+        let mut world = World::default();
+        let prefabs = sprite_prefabs();
+        prefabs.spawn(&mut world, SpriteType::PLAYER, random_location(&camera));
+        let spawn_rules = match options.get("spawn-rules") {
+            Some(path) => spawn::read_rules(path)?,
+            None => spawn::default_rules(),
+        };
+        for (sprite_type, location) in spawn::placements(&dataset, &spawn_rules) {
+            prefabs.spawn(&mut world, sprite_type, location);
+        }
+        if world.entities.iter().count() == 1 {
+            let num_enemies = 10;
+            for _ in 1..=num_enemies {
+                prefabs.spawn(&mut world, SpriteType::ENEMY, random_location(&camera));
+            }
+        }
+        let mut collected = 0;
+
+        let mut playback = gpx.as_ref()
+            .and_then(|gpx| gpx.tracks.iter().chain(&gpx.routes).find_map(|track| Playback::new(track, time_scale)));
+        if playback.is_some() {
+            prefabs.spawn(&mut world, SpriteType::TRACK, camera.center);
+        }
+        let schedule = game_schedule();
+
+        let mut zones = geofence::zones_from_dataset(&dataset, &TagFilter::parse_list(options.get("zones").unwrap_or("")));
+        if let Some(path) = options.get("zone-file") {
+            zones.extend(geofence::read_zones(path)?);
+        }
+        let mut geofence = Geofence::new(zones);
+        geofence.subscribe(Box::new(|event| match event.crossing {
+            Crossing::Enter => println!("sprite {} entered {}", event.sprite_id, event.zone_name),
+            Crossing::Exit => println!("sprite {} left {}", event.sprite_id, event.zone_name),
+        }));
+        let locator = Locator::build(&dataset);
+        sdl_components.video_subsystem.text_input().stop();
 
         'running: loop {
-            let mut time_elapsed = 0;
+            let frame_start = sdl_components.timer_subsystem.ticks64();
+            timestep.begin_frame(frame_start);
 
             for event in sdl_components.event_pump.poll_iter() {
                 match event {
-                    Event::Quit {..} |
-                    Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'running,
+                    Event::Quit {..} => break 'running,
+                    Event::KeyDown { keycode: Some(Keycode::Escape), .. } if !search_box.active => break 'running,
                     _ => {}
                 }
 
-                handle_key_events(event.clone(), &mut input);
+                handle_key_events(event.clone(), &mut input, search_box.active);
                 handle_mouse_events(event, &mut input);
             }
 
-            update_sprites(sprites, &mut input, &mut sdl_components);
+            update_search(&mut search_box, &search_index, &mut input, &mut camera, &sdl_components);
+            update_camera(&mut camera, &mut input, &sdl_components);
+            update_minimap(&mut minimap, &mut camera, &mut input);
+            if !search_box.active {
+                update_overlay(&mut overlay, &mut input);
+            }
+            if let (true, Some(path)) = (input.take_key_press(Keycode::F5), style_path) {
+                match StyleSheet::read(path) {
+                    Ok(reloaded) => {
+                        style_sheet = reloaded;
+                        map_layer.invalidate();
+                        println!("reloaded {}", path);
+                    }
+                    Err(err) => eprintln!("Error: {}: {}", path, err),
+                }
+            }
+            if let (Some(playback), false) = (&mut playback, search_box.active) {
+                update_playback(playback, &mut input);
+            }
+
+            if !search_box.active {
+                update_clock(&mut clock, &mut input);
+            }
+
+            while timestep.step() {
+                // game time, zero while paused so sprites stay put
+                let dt = clock.advance(timestep.dt());
+                if let Some(playback) = &mut playback {
+                    playback.advance(dt);
+                }
+                let (window_width, window_height) = sdl_components.canvas.output_size()?;
+                let mut tick = Tick {
+                    dt,
+                    now: clock.now(),
+                    camera: camera.clone(),
+                    controls: Controls::read(&input),
+                    track_position: playback.as_ref().map(Playback::position),
+                    window: (window_width as i32, window_height as i32),
+                    collected: 0,
+                };
+                schedule.run(&mut world, &mut tick);
+                collected += tick.collected;
+                geofence.update(world.transforms.iter().map(|(entity, transform)| (entity.to_bits(), transform.location)));
+            }
 
             sdl_components.canvas.set_draw_color(Color::RGB(255, 255, 255));
             sdl_components.canvas.clear();
 
-            draw(&mut sdl_components.canvas, sprites);
-
-            now = sdl_components.timer_subsystem.ticks64();
-            time_elapsed = now - past;
+            if let Some(basemap) = &mut basemap {
+                basemap.update(&texture_creator);
+                basemap.draw(&mut sdl_components.canvas, &camera);
+            }
+            if let Err(err) = map_layer.draw(&mut sdl_components.canvas, &texture_creator, &camera, &dataset, &lod, &style_sheet) {
+                eprintln!("Error: {:?}", err);
+            }
+            if let Some(boundary) = &boundary {
+                boundary.draw_mask(&mut sdl_components.canvas, &camera, Color::RGB(255, 255, 255));
+            }
+            if let Some(gpx) = &gpx {
+                draw_gpx(&mut sdl_components.canvas, &camera, gpx);
+            }
+            draw(&mut sdl_components.canvas, &world, &camera, timestep.alpha());
+            let markers: Vec<(LatLon, Color)> = world.renders.iter()
+                .filter_map(|(entity, render)| Some((world.transforms.get(entity)?.location, render.color)))
+                .collect();
+            minimap.draw(&mut sdl_components.canvas, &camera, &markers);
+            overlay.draw(&mut sdl_components.canvas, &camera, input.mouse_current_pos_x, input.mouse_current_pos_y);
+            if let Some(playback) = &playback {
+                playback.draw_hud(&mut sdl_components.canvas, &camera);
+            }
+            if let Some(player) = world.players.iter().find_map(|(entity, _)| world.transforms.get(entity)) {
+                draw_location(&mut sdl_components.canvas, locator.describe(player.location));
+            }
+            draw_clock(&mut sdl_components.canvas, &clock);
+            draw_search_box(&mut sdl_components.canvas, &search_box);
 
             sdl_components.canvas.line(50, 50, 200, 200, Color::RGB(255, 0, 0));
 
-            if time_elapsed >= (1000 / 60) {
-                past = now;
-
-                if frames_skipped + 1 >= 0 {
-                    sdl_components.canvas.present();
-                    fps += 1;
-                    frames_skipped = 0;
+            // taken once everything is drawn so the file matches what is presented
+            if input.take_key_press(Keycode::F12) {
+                let path = snapshot::screenshot_path();
+                match snapshot::save_canvas(&sdl_components.canvas, &path) {
+                    Ok(()) => println!("saved {}", path),
+                    Err(err) => eprintln!("Error: {:?}", err),
                 }
             }
+            input.end_frame();
 
+            sdl_components.canvas.present();
+            fps += 1;
+
+            let now = sdl_components.timer_subsystem.ticks64();
             if now - past_fps >= 1000 {
                 past_fps = now;
-                sdl_components.update_window_title(["fps: ", fps.to_string().as_str()].join("").as_str());
+                sdl_components.update_window_title(["fps: ", fps.to_string().as_str(), "  collected: ", collected.to_string().as_str()].join("").as_str());
                 fps = 0;
             }
 
-            std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+            // vsync already waits in `present`
+            if !vsync {
+                let frame_time = Duration::from_millis(now - frame_start);
+                if let Some(rest) = Duration::from_secs_f64(1.0 / max_fps).checked_sub(frame_time) {
+                    std::thread::sleep(rest);
+                }
+            }
         }
 
         Ok(())
     }
 
-    pub fn add_sprite (sprites: &mut Vec<Sprite>, sprite_type: SpriteType, sdl_components: &mut SdlComponents) {
-        let (mut window_width,mut window_height) = sdl_components.canvas.output_size().unwrap();
-        let window_width: i32 = window_width as i32;
-        let window_height: i32 = window_height as i32;
-
-        sprites.push(Sprite{
-            x: random_int(0, window_width),
-            y: random_int(0, window_height),
-            sprite_type: sprite_type,
-            red: random_int(0, 255) as u8,
-            green: random_int(0, 255) as u8,
-            blue: random_int(0, 255) as u8,
-            action_end_timestamp: 0,
-            current_direction: Direction::STOP,
-        });
+    /// what each kind of sprite is made of; a new kind is one more prefab registered here
+    fn sprite_prefabs () -> Prefabs<SpriteType> {
+        Prefabs::default()
+            .with(SpriteType::PLAYER, player_prefab)
+            .with(SpriteType::ENEMY, enemy_prefab)
+            .with(SpriteType::DEFAULT, wanderer_prefab)
+            .with(SpriteType::TRACK, track_prefab)
+            .with(SpriteType::COLLECTIBLE, collectible_prefab)
+    }
+
+    /// the square every sprite is drawn as, touched and clicked by
+    fn sprite_body (world: &mut World, entity: Entity, color: Color) {
+        world.renders.insert(entity, Render { color, size: SPRITE_SIZE });
+        world.colliders.insert(entity, Collider { size: SPRITE_SIZE });
+    }
+
+    fn random_color () -> Color {
+        Color::RGB(random_int(0, 255) as u8, random_int(0, 255) as u8, random_int(0, 255) as u8)
+    }
+
+    fn player_prefab (world: &mut World, entity: Entity) {
+        sprite_body(world, entity, random_color());
+        world.players.insert(entity, PlayerControl);
+    }
+
+    fn enemy_prefab (world: &mut World, entity: Entity) {
+        wanderer_prefab(world, entity);
+        world.healths.insert(entity, Health::new(1));
+    }
+
+    fn wanderer_prefab (world: &mut World, entity: Entity) {
+        sprite_body(world, entity, random_color());
+        world.ais.insert(entity, Ai::Wander { direction: Direction::STOP, until: 0 });
+    }
+
+    fn track_prefab (world: &mut World, entity: Entity) {
+        sprite_body(world, entity, random_color());
+        world.track_followers.insert(entity, FollowTrack);
+    }
+
+    fn collectible_prefab (world: &mut World, entity: Entity) {
+        sprite_body(world, entity, Color::RGB(255, 200, 0));
+        world.pickups.insert(entity, Pickup { value: 1 });
+    }
+
+    /// somewhere on screen
+    fn random_location (camera: &Camera) -> LatLon {
+        camera.unproject(random_int(0, camera.viewport_width as i32), random_int(0, camera.viewport_height as i32))
     }
 
-    pub fn draw (canvas: &mut WindowCanvas, sprites: &Vec<Sprite>) {
-        let rects: &mut Vec<Rect> = &mut Vec::new();
-        for sprite in sprites {
-            let rect = Rect::new(sprite.x, sprite.y, 20, 20);
-            rects.push(rect);
+    /// `alpha` is how far the frame is between the last two updates
+    pub fn draw (canvas: &mut WindowCanvas, world: &World, camera: &Camera, alpha: f64) {
+        for (entity, render) in world.renders.iter() {
+            let Some(transform) = world.transforms.get(entity) else { continue };
+            let (x, y) = camera.project(transform.interpolated(alpha));
+            let rect = Rect::new(x - render.size / 2, y - render.size / 2, render.size as u32, render.size as u32);
 
-            canvas.set_draw_color(Color::RGB(sprite.red, sprite.green, sprite.blue));
+            canvas.set_draw_color(render.color);
             canvas.fill_rect(rect).expect("Failed to draw rectangle");
         }
     }
 
-    pub fn update_sprites (sprites: &mut Vec<Sprite>, input: &mut Input, sdl_components: &mut SdlComponents) {
-        let (mut window_width,mut window_height) = sdl_components.canvas.output_size().unwrap();
-        let window_width: i32 = window_width as i32;
-        let window_height: i32 = window_height as i32;
+    fn update_search (search_box: &mut SearchBox, search_index: &SearchIndex, input: &mut Input, camera: &mut Camera, sdl_components: &SdlComponents) {
+        let text_input = sdl_components.video_subsystem.text_input();
 
-        let mut i = 0;
-        while i < sprites.len() {
-            let sprite = &mut sprites[i];
-            sprite.return_sprite_to_canvas(window_width, window_height);
-            sprite.auto_move(sdl_components);
-            
-            if sprite.sprite_type == SpriteType::PLAYER {
-                if input.is_key_down(Keycode::Up) { sprite.y -= 10 }
-                if input.is_key_down(Keycode::Down) { sprite.y += 10 }
-                if input.is_key_down(Keycode::Left) { sprite.x -= 10 }
-                if input.is_key_down(Keycode::Right) { sprite.x += 10 }
+        if !search_box.active {
+            if input.take_key_press(Keycode::F) && input.is_ctrl_down() {
+                search_box.open();
+                text_input.start();
             }
+            input.take_text();
+            return;
+        }
 
-            if input.is_mouse_btn_down(MouseButton::Left) &&
-               input.mouse_over_sprite(sprite) == true &&
-               sprite.sprite_type == SpriteType::ENEMY
-            {
-                Sprite::destroy_sprite(sprites, i);
-            } else {
-                i += 1;
+        let text = input.take_text();
+        if !text.is_empty() {
+            search_box.push_str(&text, search_index);
+        }
+
+        if input.take_key_press(Keycode::Backspace) {
+            search_box.backspace(search_index);
+        }
+        if input.take_key_press(Keycode::Tab) {
+            search_box.select_next();
+        }
+        if input.take_key_press(Keycode::Return) {
+            if let Some(hit) = search_box.selected_hit() {
+                camera.jump_to(hit.location);
+            }
+            search_box.close();
+            text_input.stop();
+        }
+        if input.take_key_press(Keycode::Escape) {
+            search_box.close();
+            text_input.stop();
+        }
+    }
+
+    fn update_camera (camera: &mut Camera, input: &mut Input, sdl_components: &SdlComponents) {
+        let (window_width, window_height) = sdl_components.canvas.output_size().unwrap();
+        camera.set_viewport(window_width, window_height);
+
+        let (drag_x, drag_y) = input.take_drag();
+        if drag_x != 0 || drag_y != 0 {
+            camera.pan(drag_x, drag_y);
+        }
+
+        let wheel = input.take_wheel();
+        if wheel != 0 {
+            camera.zoom_at(wheel as f64 * 0.5, input.mouse_current_pos_x, input.mouse_current_pos_y);
+        }
+    }
+
+    fn update_minimap (minimap: &mut Minimap, camera: &mut Camera, input: &mut Input) {
+        minimap.place(camera.viewport_width, camera.viewport_height);
+
+        if let Some((x, y)) = input.take_click(MouseButton::Left, minimap.rect()) {
+            camera.jump_to(minimap.unproject(x, y));
+        }
+    }
+
+    fn update_overlay (overlay: &mut Overlay, input: &mut Input) {
+        if input.take_key_press(Keycode::G) { overlay.graticule = !overlay.graticule; }
+        if input.take_key_press(Keycode::B) { overlay.scale_bar = !overlay.scale_bar; }
+        if input.take_key_press(Keycode::U) { overlay.imperial = !overlay.imperial; }
+        if input.take_key_press(Keycode::C) { overlay.coordinates = !overlay.coordinates; }
+    }
+
+    /// `p` pauses, `[` and `]` halve and double the time scale, `r` starts over
+    fn update_playback (playback: &mut Playback, input: &mut Input) {
+        if input.take_key_press(Keycode::P) { playback.paused = !playback.paused; }
+        if input.take_key_press(Keycode::LeftBracket) { playback.time_scale /= 2.0; }
+        if input.take_key_press(Keycode::RightBracket) { playback.time_scale *= 2.0; }
+        if input.take_key_press(Keycode::R) { playback.elapsed = 0.0; }
+    }
+
+    /// `space` pauses and resumes, `-` and `=` halve and double the game speed, `0` puts it back to normal
+    fn update_clock (clock: &mut GameClock, input: &mut Input) {
+        if input.take_key_press(Keycode::Space) { clock.toggle_pause(); }
+        if input.take_key_press(Keycode::Minus) { clock.set_scale(clock.scale() / 2.0); }
+        if input.take_key_press(Keycode::Equals) { clock.set_scale(clock.scale() * 2.0); }
+        if input.take_key_press(Keycode::Num0) { clock.set_scale(1.0); }
+    }
+
+    /// shown at the top center, only while the clock is not running at normal speed
+    fn draw_clock (canvas: &mut WindowCanvas, clock: &GameClock) {
+        let label = match (clock.paused(), clock.scale()) {
+            (true, _) => "paused".to_string(),
+            (false, scale) if scale != 1.0 => format!("x{}", scale),
+            _ => return,
+        };
+        let (width, _) = canvas.output_size().unwrap();
+        let _ = canvas.string(width as i16 / 2 - label.len() as i16 * 4, 10, &label, Color::RGB(200, 0, 0));
+    }
+
+    /// the street or area the player is in, bottom center
+    fn draw_location (canvas: &mut WindowCanvas, label: Option<String>) {
+        let Some(label) = label else { return };
+        let (width, height) = canvas.output_size().unwrap();
+        let x = width as i16 / 2 - label.len() as i16 * 4;
+        let y = height as i16 - 24;
+
+        let _ = canvas.box_(x - 6, y - 4, x + label.len() as i16 * 8 + 6, y + 12, Color::RGBA(255, 255, 255, 220));
+        let _ = canvas.string(x, y, &label, Color::RGB(0, 0, 0));
+    }
+
+    fn draw_search_box (canvas: &mut WindowCanvas, search_box: &SearchBox) {
+        if !search_box.active { return; }
+
+        let line_height = 12;
+        let height = (search_box.hits.len() as u32 + 1) * line_height + 8;
+        canvas.set_draw_color(Color::RGBA(255, 255, 255, 230));
+        canvas.fill_rect(Rect::new(10, 10, 300, height)).expect("Failed to draw search box");
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.draw_rect(Rect::new(10, 10, 300, height)).expect("Failed to draw search box");
+
+        let _ = canvas.string(14, 14, &format!("search: {}_", search_box.query), Color::RGB(0, 0, 0));
+        for (i, hit) in search_box.hits.iter().enumerate() {
+            let color = if i == search_box.selected { Color::RGB(200, 0, 0) } else { Color::RGB(60, 60, 60) };
+            let _ = canvas.string(14, 14 + (i as i16 + 1) * line_height as i16, &hit.label, color);
+        }
+    }
+
+    /// what the systems get to see of the rest of the game for one update
+    struct Tick {
+        /// game seconds since the last update
+        dt: f64,
+        /// game clock milliseconds
+        now: u64,
+        camera: Camera,
+        controls: Controls,
+        track_position: Option<LatLon>,
+        window: (i32, i32),
+        /// collectibles picked up during this update
+        collected: u32,
+    }
+
+    /// the held keys and buttons systems care about
+    struct Controls {
+        up: bool,
+        down: bool,
+        left: bool,
+        right: bool,
+        fire: bool,
+        mouse: (i32, i32),
+    }
+
+    impl Controls {
+        fn read(input: &Input) -> Controls {
+            Controls {
+                up: input.is_key_down(Keycode::Up),
+                down: input.is_key_down(Keycode::Down),
+                left: input.is_key_down(Keycode::Left),
+                right: input.is_key_down(Keycode::Right),
+                fire: input.is_mouse_btn_down(MouseButton::Left),
+                mouse: (input.mouse_current_pos_x, input.mouse_current_pos_y),
+            }
+        }
+    }
+
+    /// positions are remembered before anything moves, and the dead are cleared out last
+    fn game_schedule () -> Schedule<Tick> {
+        Schedule::default()
+            .with(remember_positions)
+            .with(wander)
+            .with(player_control)
+            .with(wrap_to_window)
+            .with(follow_track)
+            .with(click_damage)
+            .with(pickups)
+            .with(deaths)
+    }
+
+    fn remember_positions (world: &mut World, _tick: &mut Tick) {
+        for (_, transform) in world.transforms.iter_mut() {
+            transform.previous_location = transform.location;
+        }
+    }
+
+    fn wander (world: &mut World, tick: &mut Tick) {
+        let World { ais, transforms, .. } = world;
+        for (entity, ai) in ais.iter_mut() {
+            let Some(transform) = transforms.get_mut(entity) else { continue };
+            match ai {
+                Ai::Wander { direction, until } => {
+                    if *until == 0 || *until <= tick.now {
+                        *direction = Direction::from_int(random_int(0, 8)).unwrap_or(Direction::STOP);
+                        *until = random_int(0, 3000) as u64 + tick.now;
+                    }
+                    else {
+                        let step = SPRITE_SPEED * tick.dt;
+                        let (dx, dy) = direction.offset();
+                        transform.move_by(dx * step, dy * step, &tick.camera);
+                    }
+                }
+            }
+        }
+    }
+
+    /// arrow keys move the player, leaving the window on one side comes back in on the other
+    /// the player or a wanderer walking off one edge of the window comes back at the opposite one,
+    /// things that stay put or follow a track are left where they are
+    fn wrap_to_window (world: &mut World, tick: &mut Tick) {
+        let World { players, ais, transforms, .. } = world;
+        let (window_width, window_height) = tick.window;
+        for (entity, transform) in transforms.iter_mut() {
+            if players.get(entity).is_none() && ais.get(entity).is_none() { continue; }
+
+            let (x, y) = tick.camera.project(transform.location);
+            let wrapped = if x > window_width { Some((0, y)) }
+                else if x < 0 { Some((window_width, y)) }
+                else if y > window_height { Some((x, 0)) }
+                else if y < 0 { Some((x, window_height)) }
+                else { None };
+            if let Some((x, y)) = wrapped {
+                // no point blending across the screen
+                *transform = Transform::new(tick.camera.unproject(x, y));
+            }
+        }
+    }
+
+    fn player_control (world: &mut World, tick: &mut Tick) {
+        let World { players, transforms, .. } = world;
+        for (entity, _) in players.iter() {
+            let Some(transform) = transforms.get_mut(entity) else { continue };
+
+            let step = SPRITE_SPEED * tick.dt;
+            let controls = &tick.controls;
+            let dx = (controls.right as i32 - controls.left as i32) as f64 * step;
+            let dy = (controls.down as i32 - controls.up as i32) as f64 * step;
+            transform.move_by(dx, dy, &tick.camera);
+        }
+    }
+
+    fn follow_track (world: &mut World, tick: &mut Tick) {
+        let Some(position) = tick.track_position else { return };
+        let World { track_followers, transforms, .. } = world;
+        for (entity, _) in track_followers.iter() {
+            if let Some(transform) = transforms.get_mut(entity) {
+                transform.location = position;
+            }
+        }
+    }
+
+    /// holding the left button over something with health wears it down
+    fn click_damage (world: &mut World, tick: &mut Tick) {
+        if !tick.controls.fire { return; }
+
+        let World { healths, colliders, transforms, .. } = world;
+        for (entity, health) in healths.iter_mut() {
+            let (Some(collider), Some(transform)) = (colliders.get(entity), transforms.get(entity)) else { continue };
+            if collider.rect(tick.camera.project(transform.location)).contains_point(tick.controls.mouse) {
+                health.current -= 1;
             }
         }
     }
 
+    fn pickups (world: &mut World, tick: &mut Tick) {
+        let rect = |entity: Entity| Some(world.colliders.get(entity)?.rect(tick.camera.project(world.transforms.get(entity)?.location)));
+        let players: Vec<Rect> = world.players.iter().filter_map(|(entity, _)| rect(entity)).collect();
+        let picked: Vec<(Entity, u32)> = world.pickups.iter()
+            .filter(|(entity, _)| rect(*entity).is_some_and(|pickup| players.iter().any(|player| player.has_intersection(pickup))))
+            .map(|(entity, pickup)| (entity, pickup.value))
+            .collect();
+
+        for (entity, value) in picked {
+            tick.collected += value;
+            world.despawn(entity);
+        }
+    }
+
+    fn deaths (world: &mut World, _tick: &mut Tick) {
+        let dead: Vec<Entity> = world.healths.iter()
+            .filter(|(_, health)| health.current <= 0)
+            .map(|(entity, _)| entity)
+            .collect();
+
+        for entity in dead {
+            world.despawn(entity);
+        }
+    }
+
     pub struct SdlComponents {
         sdl_context: Sdl,
         video_subsystem: VideoSubsystem,
@@ -184,14 +632,18 @@ pub mod engine {
     }
 
     impl SdlComponents {
-        fn init () -> SdlComponents {
+        fn init (vsync: bool) -> SdlComponents {
             let sdl_context = sdl2::init().unwrap();
             let video_subsystem = sdl_context.video().unwrap();
             let window = video_subsystem.window("hi", 800, 600)
                 .position_centered()
                 .build()
                 .unwrap();
-            let mut canvas = window.into_canvas().build().unwrap();
+            let mut canvas_builder = window.into_canvas();
+            if vsync {
+                canvas_builder = canvas_builder.present_vsync();
+            }
+            let mut canvas = canvas_builder.build().unwrap();
             let event_pump = sdl_context.event_pump().unwrap();
             let timer_subsystem: TimerSubsystem = sdl_context.timer().unwrap();
 
@@ -211,25 +663,84 @@ pub mod engine {
 
     struct Input {
         keys: HashMap<Keycode, bool>,
+        key_presses: Vec<Keycode>,
+        key_mod: Mod,
+        text: String,
         mouse: HashMap<MouseButton, bool>,
         mouse_current_pos_x: i32,
         mouse_current_pos_y: i32,
         mouse_last_click_pos_x: i32,
         mouse_last_click_pos_y: i32,
+        mouse_drag_x: i32,
+        mouse_drag_y: i32,
+        mouse_wheel: i32,
+        mouse_clicks: Vec<(MouseButton, i32, i32)>,
     }
 
     impl Input {
         fn new() -> Self {
             Input {
                 keys: HashMap::new(),
+                key_presses: Vec::new(),
+                key_mod: Mod::NOMOD,
+                text: String::new(),
                 mouse: HashMap::new(),
                 mouse_current_pos_x: 0,
                 mouse_current_pos_y: 0,
                 mouse_last_click_pos_x: 0,
-                mouse_last_click_pos_y: 0
+                mouse_last_click_pos_y: 0,
+                mouse_drag_x: 0,
+                mouse_drag_y: 0,
+                mouse_wheel: 0,
+                mouse_clicks: Vec::new(),
+            }
+        }
+
+        /// true once per key down event, so keys can act as one-shot commands
+        fn take_key_press(&mut self, key: Keycode) -> bool {
+            match self.key_presses.iter().position(|pressed| *pressed == key) {
+                Some(index) => {
+                    self.key_presses.remove(index);
+                    true
+                }
+                None => false,
             }
         }
 
+        fn is_ctrl_down(&self) -> bool {
+            self.key_mod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD)
+        }
+
+        fn take_text(&mut self) -> String {
+            std::mem::take(&mut self.text)
+        }
+
+        fn take_drag(&mut self) -> (i32, i32) {
+            let drag = (self.mouse_drag_x, self.mouse_drag_y);
+            self.mouse_drag_x = 0;
+            self.mouse_drag_y = 0;
+            drag
+        }
+
+        fn take_wheel(&mut self) -> i32 {
+            std::mem::take(&mut self.mouse_wheel)
+        }
+
+        /// a button press inside `area` this frame, taken so nothing else reacts to it
+        fn take_click(&mut self, mouse_btn: MouseButton, area: Rect) -> Option<(i32, i32)> {
+            let index = self.mouse_clicks.iter()
+                .position(|(button, x, y)| *button == mouse_btn && area.contains_point((*x, *y)))?;
+            let (_, x, y) = self.mouse_clicks.remove(index);
+            Some((x, y))
+        }
+
+        /// drops one-shot input nobody asked for this frame
+        fn end_frame(&mut self) {
+            self.key_presses.clear();
+            self.text.clear();
+            self.mouse_clicks.clear();
+        }
+
         fn set_key(&mut self, key: Keycode, value: bool) {
             self.keys.insert(key, value);
         }
@@ -263,26 +774,24 @@ pub mod engine {
         fn last_click_mouse_pos(&mut self) -> (i32, i32) {
             (self.mouse_last_click_pos_x, self.mouse_last_click_pos_y)
         }
-
-        fn mouse_over_sprite(&mut self, sprite: &Sprite) -> bool {
-            if self.mouse_current_pos_x >= sprite.x && self.mouse_current_pos_x <= sprite.x + 20 &&
-               self.mouse_current_pos_y >= sprite.y && self.mouse_current_pos_y <= sprite.y + 20
-            {
-                return true;
-            }
-
-            false
-        }
     }
 
-    fn handle_key_events(event: Event, input: &mut Input) {
+    fn handle_key_events(event: Event, input: &mut Input, text_input_active: bool) {
         match event {
-            Event::KeyDown { keycode: Some(keycode), .. } => {
-                input.set_key(keycode, true);
+            Event::KeyDown { keycode: Some(keycode), keymod, .. } => {
+                input.key_mod = keymod;
+                input.key_presses.push(keycode);
+                if !text_input_active {
+                    input.set_key(keycode, true);
+                }
             },
-            Event::KeyUp { keycode: Some(keycode), .. } => {
+            Event::KeyUp { keycode: Some(keycode), keymod, .. } => {
+                input.key_mod = keymod;
                 input.set_key(keycode, false);
             },
+            Event::TextInput { text, .. } if text_input_active => {
+                input.text.push_str(&text);
+            },
             _ => {}
         }
     }
@@ -291,14 +800,22 @@ pub mod engine {
         match event {
             Event::MouseButtonDown { mouse_btn,x, y, .. } => {
                 input.set_mouse_btn(mouse_btn, true);
+                input.mouse_clicks.push((mouse_btn, x, y));
             },
             Event::MouseButtonUp { mouse_btn, x, y, .. } => {
                 input.set_mouse_btn(mouse_btn, false);
                 input.set_last_click_mouse_pos(x, y);
             },
-            Event::MouseMotion { x, y, .. } => {
+            Event::MouseMotion { x, y, xrel, yrel, mousestate, .. } => {
                 input.mouse_current_pos_x = x;
                 input.mouse_current_pos_y = y;
+                if mousestate.right() {
+                    input.mouse_drag_x += xrel;
+                    input.mouse_drag_y += yrel;
+                }
+            }
+            Event::MouseWheel { y, .. } => {
+                input.mouse_wheel += y;
             }
             _ => {}
         }
@@ -306,26 +823,24 @@ pub mod engine {
 }
 
 pub mod sprite {
-    use crate::random::random_int;
-    use super::engine::SdlComponents;
+    use serde::Deserialize;
 
-    #[derive(PartialEq)]
-pub struct Sprite {
-        pub x: i32,
-        pub y: i32,
-        pub red: u8,
-        pub green: u8,
-        pub blue: u8,
-        pub sprite_type: SpriteType,
-        pub current_direction: Direction,
-        pub action_end_timestamp: i32,
-    }
+    /// sprites are squares this many pixels across
+    pub const SPRITE_SIZE: i32 = 20;
+    /// how fast sprites walk, in pixels per second
+    pub const SPRITE_SPEED: f64 = 600.0;
 
-    #[derive(PartialEq)]
+    /// which prefab `engine::sprite_prefabs` builds an entity from
+    #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq)]
+    #[serde(rename_all = "lowercase")]
     pub enum SpriteType {
         PLAYER,
         ENEMY,
         DEFAULT,
+        /// follows a gpx track playback instead of moving by itself
+        TRACK,
+        /// stays put until the player walks over it
+        COLLECTIBLE,
     }
 
     #[derive(PartialEq, Eq)]
@@ -356,66 +871,49 @@ pub struct Sprite {
                 _ => None,
             }
         }
-    }
-
-    impl Sprite {
-        pub fn auto_move (&mut self, sdl_components: &mut SdlComponents) {
-            if self.sprite_type == SpriteType::PLAYER { return; }
-
-            let now = sdl_components.timer_subsystem.ticks64();
-
-            if self.action_end_timestamp == 0 || self.action_end_timestamp <= now as i32 {
-                self.current_direction = Direction::from_int(random_int(0, 8)).unwrap_or(Direction::STOP);
-                self.action_end_timestamp = random_int(0, 3000) + now as i32;
-            }
-            else {
-                match self.current_direction {
-                    Direction::STOP => { },
-                    Direction::UP => {self.y += 10}
-                    Direction::RIGHT => {self.x += 10}
-                    Direction::DOWN => {self.y -= 10}
-                    Direction::LEFT => {self.x -= 10}
-                    Direction::UPRIGHT => {self.y += 10; self.x += 10}
-                    Direction::DOWNRIGHT => {self.y -= 10; self.x += 10}
-                    Direction::UPLEFT => {self.y -= 10; self.x -= 10}
-                    Direction::DOWNLEFT => {self.y += 10; self.x -= 10}
-                };
-            }
-        }
 
-        pub fn return_sprite_to_canvas(&mut self, window_width: i32, window_height: i32) {
-            if self.x > window_width {
-                self.x = 0
-            }
-            else if self.x < 0 {
-                self.x = window_width
+        /// screen pixels moved per pixel of step
+        pub fn offset(&self) -> (f64, f64) {
+            match self {
+                Direction::STOP => (0.0, 0.0),
+                Direction::UP => (0.0, 1.0),
+                Direction::RIGHT => (1.0, 0.0),
+                Direction::DOWN => (0.0, -1.0),
+                Direction::LEFT => (-1.0, 0.0),
+                Direction::UPRIGHT => (1.0, 1.0),
+                Direction::DOWNRIGHT => (1.0, -1.0),
+                Direction::UPLEFT => (-1.0, -1.0),
+                Direction::DOWNLEFT => (-1.0, 1.0),
             }
-            else if self.y > window_height {
-                self.y = 0
-            }
-            else if self.y < 0 {
-                self.y = window_height
-            }
-        }
-
-        pub fn destroy_sprite(sprites: &mut Vec<Sprite>, index: usize) {
-            sprites.remove(index);
         }
     }
 }
 
 pub mod random {
+    use rand::seq::SliceRandom;
     use rand::Rng;
 
     pub fn random_int(min: i32, max: i32) -> i32 {
         let mut rng = rand::thread_rng();
         rng.gen_range(min..max)
     }
+
+    pub fn shuffle<T>(items: &mut [T]) {
+        items.shuffle(&mut rand::thread_rng());
+    }
 }
 
 pub mod api {
     use reqwest;
     use std::error::Error;
+    use std::io::{self, Read};
+
+    use tokio::sync::mpsc::{channel, Receiver};
+
+    pub const OVERPASS_URL: &str = "https://overpass-api.de/api/interpreter";
+
+    /// how many response chunks may wait for the reader before the download pauses
+    const CHUNK_BACKLOG: usize = 16;
 
     pub async fn post(uri: &str, body: String) -> Result<String, Box<dyn Error>> {
         let client = reqwest::Client::new();
@@ -436,479 +934,5401 @@ pub mod api {
             Err("HTTP request failed".into())
         }
     }
+
+    /// like `post`, but hands the body over chunk by chunk as it arrives instead of collecting it
+    ///
+    /// the returned reader blocks, so it has to be consumed off the async runtime (e.g. in `spawn_blocking`)
+    pub async fn post_stream(uri: &str, body: String) -> Result<ChunkReader, Box<dyn Error>> {
+        let client = reqwest::Client::new();
+        let mut response = client
+            .post(uri)
+            .body(body)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err("HTTP request failed".into());
+        }
+
+        let (sender, receiver) = channel(CHUNK_BACKLOG);
+        tokio::spawn(async move {
+            loop {
+                let message = match response.chunk().await {
+                    Ok(Some(chunk)) => Ok(chunk.to_vec()),
+                    Ok(None) => break,
+                    Err(err) => Err(err.to_string()),
+                };
+                let failed = message.is_err();
+                if sender.send(message).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        Ok(ChunkReader { chunks: receiver, current: Vec::new(), position: 0 })
+    }
+
+    /// blocking `Read` over the chunks `post_stream` receives
+    pub struct ChunkReader {
+        chunks: Receiver<Result<Vec<u8>, String>>,
+        current: Vec<u8>,
+        position: usize,
+    }
+
+    impl Read for ChunkReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            while self.position == self.current.len() {
+                match self.chunks.blocking_recv() {
+                    Some(Ok(chunk)) => {
+                        self.current = chunk;
+                        self.position = 0;
+                    }
+                    Some(Err(err)) => return Err(io::Error::other(err)),
+                    None => return Ok(0),
+                }
+            }
+
+            let count = buf.len().min(self.current.len() - self.position);
+            buf[..count].copy_from_slice(&self.current[self.position..self.position + count]);
+            self.position += count;
+            Ok(count)
+        }
+    }
 }
 
 pub mod osm {
     use std::collections::HashMap;
     use std::error::Error;
 
+    use serde::de::DeserializeOwned;
     use serde::Deserialize;
-    use serde_xml_rs::from_str;
-    use crate::api::post;
+    use crate::api::{post, OVERPASS_URL};
+    use crate::geo::{BBox, LatLon};
+    use crate::osm_reader::{stream_overpass, Element};
+
+    /// what every element kind shares, so fetching and parsing is written once for all of them
+    pub trait OsmElement: Sized + Send + 'static {
+        /// overpass statement and xml tag name, e.g. `way`
+        const KIND: &'static str;
+        /// one entry of an overpass json `elements` array
+        type Json: DeserializeOwned;
+
+        fn id(&self) -> i64;
+        fn meta(&self) -> &Metadata;
+        fn from_json(json: Self::Json) -> Self;
+        /// `None` for elements of another kind
+        fn from_element(element: Element) -> Option<Self>;
+    }
 
-    #[derive(Debug, Deserialize)]
-    struct JsonNodeRoot {
-        elements: Vec<JsonNode>
+    /// the part of the map a query covers
+    pub enum Scope {
+        BBox(BBox),
+        /// inside the boundary relation with this id, through overpass' `area(3600000000 + id)`
+        Area(i64),
     }
 
-    #[derive(Debug, Deserialize)]
-    struct JsonNode {
-        id: i64,
-        lat: f32,
-        lon: f32,
-        tags: Option<HashMap<String, String>>,
-        #[serde(rename = "type")]
-        element_type: String,
+    impl Scope {
+        fn select(&self, kind: &str) -> String {
+            match self {
+                Scope::BBox(bbox) => format!("{}({});", kind, bbox),
+                Scope::Area(relation_id) => format!("area({})->.a; {}(area.a);", 3_600_000_000 + relation_id, kind),
+            }
+        }
+    }
+
+    /// every element of kind `T` in `scope`, e.g. `fetch::<Way>(OutputFormat::XML, GeometryMode::Geom, &Scope::BBox(bbox))`
+    pub async fn fetch<T: OsmElement>(output_format: OutputFormat, geometry_mode: GeometryMode, scope: &Scope) -> Result<HashMap<i64, T>, Box<dyn Error>> {
+        let query = format!("[out:{}]; {} {}", get_output_format(&output_format), scope.select(T::KIND), get_out_statement(&geometry_mode));
+        run_query(output_format, query).await
+    }
+
+    /// runs a hand-written query whose `[out:...]` matches `output_format`, keeping the elements of kind `T`
+    pub async fn run_query<T: OsmElement>(output_format: OutputFormat, query: String) -> Result<HashMap<i64, T>, Box<dyn Error>> {
+        match output_format {
+            OutputFormat::JSON => parse_json(&post(OVERPASS_URL, query).await?),
+            // xml is parsed while it downloads, large responses never sit in memory as a whole
+            OutputFormat::XML => stream_overpass(query).await,
+        }
     }
 
     #[derive(Debug, Deserialize)]
-    struct XmlNodeRoot {
-        node: Vec<XmlNode>
+    struct JsonRoot<T> {
+        elements: Vec<T>
+    }
+
+    fn parse_json<T: OsmElement> (string: &str) -> Result<HashMap<i64, T>, Box<dyn Error>> {
+        let root: JsonRoot<T::Json> = serde_json::from_str(string)?;
+        Ok(root.elements.into_iter()
+            .map(T::from_json)
+            .map(|element| (element.id(), element))
+            .collect())
     }
 
     #[derive(Debug, Deserialize)]
-    struct XmlNode {
+    pub struct JsonNode {
         id: i64,
-        lat: f32,
-        lon: f32,
-        #[serde(rename = "tag", default)]
-        tags: Vec<XmlTag>,
+        lat: f64,
+        lon: f64,
+        tags: Option<HashMap<String, String>>,
+        #[serde(flatten)]
+        meta: Metadata,
+        #[serde(rename = "type")]
+        element_type: String,
     }
 
-    #[derive(Debug, Deserialize)]
-    struct XmlTag {
-        #[serde(rename = "k")]
-        key: String,
-        #[serde(rename = "v")]
-        value: String,
+    /// present when the query asks for `out meta`
+    #[derive(Clone, Debug, Default, Deserialize)]
+    pub struct Metadata {
+        pub version: Option<u32>,
+        pub timestamp: Option<String>,
+        pub uid: Option<i64>,
+        pub user: Option<String>,
+        pub changeset: Option<i64>,
     }
 
+    #[derive(Clone)]
     pub struct Node {
-        lat: f32,
-        lon: f32,
-        tags: HashMap<String, String>,
+        pub id: i64,
+        pub meta: Metadata,
+        pub lat: f64,
+        pub lon: f64,
+        pub tags: HashMap<String, String>,
     }
 
     impl Node {
-        pub async fn get_nodes(output_format: OutputFormat, min_lat: f32, min_lon: f32, max_lat: f32, max_lon: f32) -> Result<HashMap<i64, Node>, Box<dyn Error>> {
-            let body = Node::build_query(&output_format, min_lat, min_lon, max_lat, max_lon);
-            let response_body = match post("https://overpass-api.de/api/interpreter", body).await {
-                Ok(response) => response,
-                Err(err) => {
-                    eprintln!("Error: {:?}", err);
-                    return Err(err);
-                }
-            };
+        pub fn location(&self) -> LatLon {
+            LatLon::new(self.lat, self.lon)
+        }
+    }
 
-            let result = match output_format {
-                OutputFormat::JSON => Node::parse_json(&response_body),
-                OutputFormat::XML => Node::parse_xml(&response_body),
-            };
+    impl OsmElement for Node {
+        const KIND: &'static str = "node";
+        type Json = JsonNode;
 
-            Ok(result)
+        fn id(&self) -> i64 { self.id }
+        fn meta(&self) -> &Metadata { &self.meta }
+
+        fn from_json(json: JsonNode) -> Node {
+            Node { id: json.id, meta: json.meta, lat: json.lat, lon: json.lon, tags: json.tags.unwrap_or_default() }
         }
 
-        fn build_query (output_format: &OutputFormat, min_lat: f32, min_lon: f32, max_lat: f32, max_lon: f32) -> String {
-            format!("[out:{}]; node({}, {}, {}, {}); out;", get_output_format(&output_format), min_lat, min_lon, max_lat, max_lon)
+        fn from_element(element: Element) -> Option<Node> {
+            match element {
+                Element::Node(node) => Some(node),
+                _ => None,
+            }
         }
+    }
 
-        fn parse_json (string: &str) -> HashMap<i64, Node> {
-            let result: JsonNodeRoot = serde_json::from_str(string).unwrap();
-            let mut deserialized_nodes: Vec<JsonNode> = Vec::new();
-            let mut nodes: HashMap<i64, Node> = HashMap::new();
+    /// how much geometry overpass inlines into ways and relations
+    #[derive(Clone, Copy)]
+    pub enum GeometryMode {
+        /// only node references, coordinates have to come from a separate node query
+        None,
+        /// `out geom;`, every way node and relation member with its coordinates
+        Geom,
+        /// `out center;`, a single point per way or relation
+        Center,
+    }
 
-            for element in result.elements {
-                let mut tags: Option<HashMap<String, String>> = None;
-                if let Some(mut element_tags) = element.tags {
-                    let mut temp_tag = HashMap::new();
-                    for (key, value) in element_tags {
-                        temp_tag.insert(key, value);
-                    }
+    fn get_out_statement (geometry_mode: &GeometryMode) -> &'static str {
+        match geometry_mode {
+            GeometryMode::None => "out meta;",
+            GeometryMode::Geom => "out meta geom;",
+            GeometryMode::Center => "out meta center;",
+        }
+    }
 
-                    tags = Some(temp_tag);
-                }
+    /// a geometry with any missing point (e.g. clipped by `out geom(bbox)`) is dropped so callers fall back to node lookups
+    pub fn complete_geometry (geometry: Option<Vec<Option<LatLon>>>) -> Option<Vec<LatLon>> {
+        geometry
+            .and_then(|points| points.into_iter().collect::<Option<Vec<LatLon>>>())
+            .filter(|points| !points.is_empty())
+    }
 
-                deserialized_nodes.push(
-                    JsonNode {
-                        id: element.id,
-                        lat: element.lat,
-                        lon: element.lon,
-                        tags,
-                        element_type: "node".to_string(),
-                    }
-                );
-            }
+    #[derive(Debug, Deserialize)]
+    pub struct JsonWay {
+        id: i64,
+        nodes: Vec<i64>,
+        tags: Option<HashMap<String, String>>,
+        geometry: Option<Vec<Option<LatLon>>>,
+        bounds: Option<BBox>,
+        center: Option<LatLon>,
+        #[serde(flatten)]
+        meta: Metadata,
+        #[serde(rename = "type")]
+        element_type: String,
+    }
 
-            for d_node in deserialized_nodes {
-                nodes.insert(d_node.id, Node { lat: d_node.lat, lon: d_node.lon, tags: d_node.tags.unwrap_or(HashMap::new()) });
-            }
+    #[derive(Clone)]
+    pub struct Way {
+        pub id: i64,
+        pub meta: Metadata,
+        pub nodes: Vec<i64>,
+        pub tags: HashMap<String, String>,
+        /// inline coordinates from `out geom`, one per entry in `nodes`
+        pub geometry: Option<Vec<LatLon>>,
+        pub bounds: Option<BBox>,
+        pub center: Option<LatLon>,
+    }
 
-            nodes
+    impl Way {
+        /// ways without node references (vector tiles, clipped pieces) go by their inline geometry
+        pub fn is_closed(&self) -> bool {
+            match (&self.geometry, self.nodes.is_empty()) {
+                (Some(geometry), true) => geometry.len() > 3 && geometry.first() == geometry.last(),
+                _ => self.nodes.len() > 2 && self.nodes.first() == self.nodes.last(),
+            }
         }
 
-        fn parse_xml (string: &str) -> HashMap<i64, Node> {
-            let result: XmlNodeRoot = from_str(string).unwrap();
-            let mut deserialized_nodes: Vec<XmlNode> = Vec::new();
-            let mut nodes: HashMap<i64, Node> = HashMap::new();
+        /// closed ways are only areas when their tags say so, a closed `highway` is still a line
+        pub fn is_area(&self) -> bool {
+            if !self.is_closed() { return false; }
 
-            for element in result.node {
-                let mut tags: Vec<XmlTag> = Vec::new();
-                if let mut element_tags = element.tags {
-                    for tag in element_tags {
-                        tags.push(XmlTag { key: tag.key, value: tag.value });
-                    }
-                }
+            match self.tags.get("area").map(|value| value.as_str()) {
+                Some("yes") => return true,
+                Some("no") => return false,
+                _ => {}
+            }
 
-                deserialized_nodes.push(
-                    XmlNode {
-                        id: element.id,
-                        lat: element.lat,
-                        lon: element.lon,
-                        tags,
-                    }
-                );
+            const AREA_KEYS: [&str; 12] = [
+                "building", "landuse", "leisure", "amenity", "shop", "place",
+                "tourism", "man_made", "historic", "aeroway", "military", "building:part",
+            ];
+            if AREA_KEYS.iter().any(|key| self.tags.contains_key(*key)) {
+                return true;
             }
 
-            for d_node in deserialized_nodes {
-                let mut tags: HashMap<String, String> = HashMap::new();
-                for tag in d_node.tags {
-                    tags.insert(tag.key, tag.value);
-                }
+            match self.tags.get("natural").map(|value| value.as_str()) {
+                Some("coastline") | Some("cliff") | Some("tree_row") | Some("ridge") => false,
+                Some(_) => true,
+                None => self.tags.get("waterway").map(|value| value == "riverbank").unwrap_or(false),
+            }
+        }
 
-                nodes.insert(d_node.id, Node { lat: d_node.lat, lon: d_node.lon, tags });
+        /// points of the way, preferring inline geometry over looking up `nodes`
+        pub fn coordinates(&self, nodes: &HashMap<i64, Node>) -> Vec<LatLon> {
+            match &self.geometry {
+                Some(geometry) => geometry.clone(),
+                None => self.nodes.iter()
+                    .filter_map(|id| nodes.get(id))
+                    .map(|node| node.location())
+                    .collect(),
             }
+        }
+    }
+
+    impl OsmElement for Way {
+        const KIND: &'static str = "way";
+        type Json = JsonWay;
+
+        fn id(&self) -> i64 { self.id }
+        fn meta(&self) -> &Metadata { &self.meta }
+
+        fn from_json(json: JsonWay) -> Way {
+            Way {
+                id: json.id,
+                meta: json.meta,
+                nodes: json.nodes,
+                tags: json.tags.unwrap_or_default(),
+                geometry: complete_geometry(json.geometry),
+                bounds: json.bounds,
+                center: json.center,
+            }
+        }
 
-            nodes
+        fn from_element(element: Element) -> Option<Way> {
+            match element {
+                Element::Way(way) => Some(way),
+                _ => None,
+            }
         }
     }
 
-    #[derive(Debug, Deserialize)]
-    struct JsonWayRoot {
-        elements: Vec<JsonWay>
+    #[derive(Clone, Debug)]
+    pub struct RelationMember {
+        pub id: i64,
+        pub role: String,
+        pub element_type: String,
+        /// node members under `out geom`
+        pub location: Option<LatLon>,
+        /// way members under `out geom`
+        pub geometry: Option<Vec<LatLon>>,
     }
 
     #[derive(Debug, Deserialize)]
-    struct JsonWay {
+    pub struct JsonRelationMember {
+        #[serde(rename = "ref")]
         id: i64,
-        nodes: Vec<i64>,
-        tags: Option<HashMap<String, String>>,
+        role: String,
         #[serde(rename = "type")]
         element_type: String,
+        lat: Option<f64>,
+        lon: Option<f64>,
+        geometry: Option<Vec<Option<LatLon>>>,
     }
 
     #[derive(Debug, Deserialize)]
-    struct XmlWayRoot {
-        way: Vec<XmlWay>
+    pub struct JsonRelation {
+        id: i64,
+        members: Vec<JsonRelationMember>,
+        tags: HashMap<String, String>,
+        bounds: Option<BBox>,
+        center: Option<LatLon>,
+        #[serde(flatten)]
+        meta: Metadata,
+        #[serde(rename = "type")]
+        element_type: String,
     }
 
-    #[derive(Debug, Deserialize)]
-    struct XmlWayNode {
-        #[serde(rename = "ref")]
-        node: i64
+    #[derive(Clone)]
+    pub struct Relation {
+        pub id: i64,
+        pub meta: Metadata,
+        /// in document order, which matters for routes and when writing the relation back out
+        pub members: Vec<RelationMember>,
+        pub tags: HashMap<String, String>,
+        pub bounds: Option<BBox>,
+        pub center: Option<LatLon>,
     }
 
-    #[derive(Debug, Deserialize)]
-    struct XmlWay {
-        id: i64,
-        #[serde(rename = "nd")]
-        nodes: Vec<XmlWayNode>,
-        #[serde(rename = "tag", default)]
-        tags: Vec<XmlTag>,
+    impl Relation {
+        pub fn ways(&self) -> impl Iterator<Item = &RelationMember> {
+            self.members.iter().filter(|member| member.element_type == "way")
+        }
 
+        pub fn nodes(&self) -> impl Iterator<Item = &RelationMember> {
+            self.members.iter().filter(|member| member.element_type == "node")
+        }
     }
 
-    pub struct Way {
-        nodes: Vec<i64>,
-        tags: HashMap<String, String>
+    impl OsmElement for Relation {
+        const KIND: &'static str = "relation";
+        type Json = JsonRelation;
+
+        fn id(&self) -> i64 { self.id }
+        fn meta(&self) -> &Metadata { &self.meta }
+
+        fn from_json(json: JsonRelation) -> Relation {
+            let members = json.members.into_iter()
+                .map(|member| RelationMember {
+                    id: member.id,
+                    role: member.role,
+                    element_type: member.element_type,
+                    location: member.lat.zip(member.lon).map(|(lat, lon)| LatLon { lat, lon }),
+                    geometry: complete_geometry(member.geometry),
+                })
+                .collect();
+
+            Relation {
+                id: json.id,
+                meta: json.meta,
+                members,
+                tags: json.tags,
+                bounds: json.bounds,
+                center: json.center,
+            }
+        }
+
+        fn from_element(element: Element) -> Option<Relation> {
+            match element {
+                Element::Relation(relation) => Some(relation),
+                _ => None,
+            }
+        }
     }
 
-    impl Way {
-        pub async fn get_nodes(output_format: OutputFormat, min_lat: f32, min_lon: f32, max_lat: f32, max_lon: f32) -> Result<HashMap<i64, Way>, Box<dyn Error>> {
-            let body = Way::build_query(&output_format, min_lat, min_lon, max_lat, max_lon);
-            let response_body = match post("https://overpass-api.de/api/interpreter", body).await {
-                Ok(response) => response,
-                Err(err) => {
-                    eprintln!("Error: {:?}", err);
-                    return Err(err);
-                }
-            };
+    #[derive(Clone, Copy)]
+    pub enum OutputFormat {
+        JSON,
+        XML
+    }
 
-            let result = match output_format {
-                OutputFormat::JSON => Way::parse_json(&response_body),
-                OutputFormat::XML => Way::parse_xml(&response_body),
-            };
+    fn get_output_format (format: &OutputFormat) -> String {
+        match format {
+            OutputFormat::JSON => "json".to_string(),
+            OutputFormat::XML => "xml".to_string(),
+        }
+    }
+
+    /// `key=value`, `key=*` or just `key`
+    pub struct TagFilter {
+        pub key: String,
+        pub value: Option<String>,
+    }
 
-            Ok(result)
+    impl TagFilter {
+        pub fn parse(filter: &str) -> TagFilter {
+            match filter.split_once('=') {
+                Some((key, "*")) => TagFilter { key: key.trim().to_string(), value: None },
+                Some((key, value)) => TagFilter { key: key.trim().to_string(), value: Some(value.trim().to_string()) },
+                None => TagFilter { key: filter.trim().to_string(), value: None },
+            }
         }
 
-        fn build_query (output_format: &OutputFormat, min_lat: f32, min_lon: f32, max_lat: f32, max_lon: f32) -> String {
-            format!("[out:{}]; way({}, {}, {}, {}); out;", get_output_format(&output_format), min_lat, min_lon, max_lat, max_lon)
+        pub fn parse_list(filters: &str) -> Vec<TagFilter> {
+            filters.split(',')
+                .filter(|filter| !filter.trim().is_empty())
+                .map(TagFilter::parse)
+                .collect()
         }
 
-        fn parse_json (string: &str) -> HashMap<i64, Way> {
-            let result: JsonWayRoot = serde_json::from_str(string).unwrap();
-            let mut deserialized_ways: Vec<JsonWay> = Vec::new();
-            let mut ways: HashMap<i64, Way> = HashMap::new();
+        pub fn matches(&self, tags: &HashMap<String, String>) -> bool {
+            match (tags.get(&self.key), &self.value) {
+                (Some(_), None) => true,
+                (Some(value), Some(expected)) => value == expected,
+                (None, _) => false,
+            }
+        }
+    }
+}
 
-            for element in result.elements {
-                let mut nodes: Vec<i64> = Vec::new();
-                if let mut element_nodes = element.nodes {
-                    let mut temp_nodes:  Vec<i64> = Vec::new();
-                    for ref_id in element_nodes {
-                        temp_nodes.push(ref_id);
-                    }
-                    nodes = temp_nodes;
-                }
+pub mod camera {
+    use crate::geo::{BBox, LatLon, Projection};
 
-                let mut tags: Option<HashMap<String, String>> = None;
-                if let Some(mut element_tags) = element.tags {
-                    let mut temp_tags: HashMap<String, String> = HashMap::new();
-                    for (key, value) in element_tags {
-                        temp_tags.insert(key, value);
-                    }
-                    tags = Some(temp_tags);
-                }
+    pub const TILE_SIZE: f64 = 256.0;
+    pub const MIN_ZOOM: f64 = 1.0;
+    pub const MAX_ZOOM: f64 = 22.0;
 
-                deserialized_ways.push(
-                    JsonWay {
-                        id: element.id,
-                        nodes,
-                        tags,
-                        element_type: "way".to_string(),
-                    }
-                );
-            }
+    #[derive(Clone)]
+    pub struct Camera {
+        pub center: LatLon,
+        pub zoom: f64,
+        pub projection: Projection,
+        pub viewport_width: u32,
+        pub viewport_height: u32,
+    }
 
-            for d_way in deserialized_ways {
-                ways.insert(d_way.id, Way { nodes: d_way.nodes, tags: d_way.tags.unwrap_or(HashMap::new()) });
+    impl Camera {
+        pub fn new(center: LatLon, zoom: f64, viewport_width: u32, viewport_height: u32) -> Camera {
+            Camera {
+                center,
+                zoom: zoom.clamp(MIN_ZOOM, MAX_ZOOM),
+                projection: Projection::WebMercator,
+                viewport_width,
+                viewport_height,
             }
+        }
+
+        pub fn fit_bbox(bbox: &BBox, viewport_width: u32, viewport_height: u32) -> Camera {
+            let projection = Projection::WebMercator;
+            let (min_x, max_y) = projection.to_world(LatLon::new(bbox.min_lat, bbox.min_lon));
+            let (max_x, min_y) = projection.to_world(LatLon::new(bbox.max_lat, bbox.max_lon));
+            let zoom_x = (viewport_width as f64 / ((max_x - min_x) * TILE_SIZE)).log2();
+            let zoom_y = (viewport_height as f64 / ((max_y - min_y) * TILE_SIZE)).log2();
+            let center = projection.from_world((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
 
-            ways
+            Camera::new(center, zoom_x.min(zoom_y), viewport_width, viewport_height)
         }
 
-        fn parse_xml (string: &str) -> HashMap<i64, Way> {
-            let result: XmlWayRoot = from_str(string).unwrap();
-            let mut deserialized_ways: Vec<XmlWay> = Vec::new();
-            let mut ways: HashMap<i64, Way> = HashMap::new();
+        pub fn set_viewport(&mut self, viewport_width: u32, viewport_height: u32) {
+            self.viewport_width = viewport_width;
+            self.viewport_height = viewport_height;
+        }
 
-            for element in result.way {
-                let mut nodes: Vec<XmlWayNode> = Vec::new();
-                if let mut element_nodes = element.nodes {
-                    let mut temp_nodes:  Vec<XmlWayNode> = Vec::new();
-                    for ref_id in element_nodes {
-                        temp_nodes.push(ref_id);
-                    }
-                    nodes = temp_nodes;
-                }
+        pub fn world_size(&self) -> f64 {
+            TILE_SIZE * 2f64.powf(self.zoom)
+        }
 
-                let mut tags: Vec<XmlTag> = Vec::new();
-                if let mut element_tags = element.tags {
-                    for tag in element_tags {
-                        tags.push(XmlTag { key: tag.key, value: tag.value });
-                    }
-                }
+        pub fn project(&self, point: LatLon) -> (i32, i32) {
+            let (x, y) = self.projection.to_world(point);
+            let (center_x, center_y) = self.projection.to_world(self.center);
+            let world_size = self.world_size();
+
+            (
+                ((x - center_x) * world_size + self.viewport_width as f64 / 2.0).round() as i32,
+                ((y - center_y) * world_size + self.viewport_height as f64 / 2.0).round() as i32,
+            )
+        }
+
+        pub fn unproject(&self, x: i32, y: i32) -> LatLon {
+            let (center_x, center_y) = self.projection.to_world(self.center);
+            let world_size = self.world_size();
+
+            self.projection.from_world(
+                center_x + (x as f64 - self.viewport_width as f64 / 2.0) / world_size,
+                center_y + (y as f64 - self.viewport_height as f64 / 2.0) / world_size,
+            )
+        }
+
+        pub fn jump_to(&mut self, center: LatLon) {
+            self.center = center;
+        }
+
+        pub fn pan(&mut self, dx: i32, dy: i32) {
+            let (center_x, center_y) = self.projection.to_world(self.center);
+            let world_size = self.world_size();
+
+            self.center = self.projection.from_world(center_x - dx as f64 / world_size, center_y - dy as f64 / world_size);
+        }
+
+        /// zooms by `delta` levels while keeping the point under (x, y) in place
+        pub fn zoom_at(&mut self, delta: f64, x: i32, y: i32) {
+            let point = self.unproject(x, y);
+            self.zoom = (self.zoom + delta).clamp(MIN_ZOOM, MAX_ZOOM);
+
+            let (moved_x, moved_y) = self.project(point);
+            self.pan(x - moved_x, y - moved_y);
+        }
+
+        /// the area currently on screen
+        pub fn viewport_bbox(&self) -> BBox {
+            let top_left = self.unproject(0, 0);
+            let bottom_right = self.unproject(self.viewport_width as i32, self.viewport_height as i32);
+
+            BBox::new(bottom_right.lat, top_left.lon, top_left.lat, bottom_right.lon)
+        }
+    }
+}
+
+pub mod style {
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::fs;
+
+    use sdl2::pixels::Color;
+    use serde::Deserialize;
+
+    pub struct Style {
+        pub color: Color,
+        pub width: u8,
+        pub fill: Option<Color>,
+    }
+
+    pub struct StyleRule {
+        pub key: String,
+        pub value: Option<String>,
+        pub style: Style,
+    }
 
-                deserialized_ways.push(
-                    XmlWay {
-                        id: element.id,
-                        nodes,
-                        tags,
+    pub struct StyleSheet {
+        pub rules: Vec<StyleRule>,
+        pub fallback: Style,
+        pub point: Style,
+    }
+
+    impl StyleSheet {
+        /// first matching rule wins, so specific values go before `key=*` rules
+        pub fn style_for(&self, tags: &HashMap<String, String>) -> &Style {
+            for rule in &self.rules {
+                if let Some(value) = tags.get(&rule.key) {
+                    if rule.value.is_none() || rule.value.as_ref() == Some(value) {
+                        return &rule.style;
                     }
-                );
+                }
             }
 
-            for d_way in deserialized_ways {
-                let mut nodes: Vec<i64> = Vec::new();
-                for node in d_way.nodes {
-                    nodes.push(node.node)
-                }
-                let mut tags: HashMap<String, String> = HashMap::new();
-                for tag in d_way.tags {
-                    tags.insert(tag.key, tag.value);
-                }
+            &self.fallback
+        }
 
-                ways.insert(d_way.id, Way { nodes, tags });
-            }
+        /// a json style sheet, see `StyleFile`; `fallback` and `point` default to the built in ones
+        pub fn read(path: &str) -> Result<StyleSheet, Box<dyn Error>> {
+            let file: StyleFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+            let defaults = StyleSheet::default();
+
+            let rules = file.rules.into_iter()
+                .map(|rule| Ok(StyleRule { key: rule.key, value: rule.value, style: rule.style.to_style()? }))
+                .collect::<Result<Vec<StyleRule>, Box<dyn Error>>>()?;
+            let fallback = file.fallback.map(|style| style.to_style()).transpose()?.unwrap_or(defaults.fallback);
+            let point = file.point.map(|style| style.to_style()).transpose()?.unwrap_or(defaults.point);
 
-            ways
+            Ok(StyleSheet { rules, fallback, point })
         }
     }
 
-    #[derive(Debug, Deserialize)]
-    struct JsonRelationRoot {
-        elements: Vec<JsonRelation>
+    /// `{ "rules": [{ "key": "highway", "value": "primary", "color": "#fcd6a4", "width": 4 }], "fallback": { .. }, "point": { .. } }`
+    ///
+    /// leaving out `value` matches any value, `fill` is only used for areas
+    #[derive(Deserialize)]
+    struct StyleFile {
+        rules: Vec<StyleFileRule>,
+        fallback: Option<StyleFileEntry>,
+        point: Option<StyleFileEntry>,
     }
 
-    #[derive(Debug, Deserialize)]
-    struct RelationMember {
-        #[serde(rename = "ref")]
-        id: i64,
-        role: String,
-        #[serde(rename = "type")]
-        element_type: String,
+    #[derive(Deserialize)]
+    struct StyleFileRule {
+        key: String,
+        value: Option<String>,
+        #[serde(flatten)]
+        style: StyleFileEntry,
     }
 
-    #[derive(Debug, Deserialize)]
-    struct JsonRelation {
-        id: i64,
-        members: Vec<RelationMember>,
-        tags: HashMap<String, String>,
-        #[serde(rename = "type")]
-        element_type: String,
+    #[derive(Deserialize)]
+    struct StyleFileEntry {
+        color: String,
+        width: u8,
+        fill: Option<String>,
     }
 
-    #[derive(Debug, Deserialize)]
-    struct XmlRelationRoot {
-        #[serde(rename = "relation")]
-        relations: Vec<XmlRelation>,
+    impl StyleFileEntry {
+        fn to_style(&self) -> Result<Style, Box<dyn Error>> {
+            Ok(Style { color: parse_color(&self.color)?, width: self.width, fill: self.fill.as_deref().map(parse_color).transpose()? })
+        }
     }
 
-    #[derive(Debug, Deserialize)]
-    struct XmlRelation {
-        id: i64,
-        #[serde(rename = "member")]
-        members: Vec<RelationMember>,
-        #[serde(rename = "tag")]
-        tags: Vec<XmlTag>,
+    /// `#rrggbb` or `#rrggbbaa`
+    pub fn parse_color(value: &str) -> Result<Color, Box<dyn Error>> {
+        let hex = value.strip_prefix('#').unwrap_or(value);
+        let channel = |index: usize| -> Result<u8, Box<dyn Error>> {
+            Ok(u8::from_str_radix(hex.get(index..index + 2).ok_or(format!("bad color: {}", value))?, 16)?)
+        };
+
+        match hex.len() {
+            6 => Ok(Color::RGB(channel(0)?, channel(2)?, channel(4)?)),
+            8 => Ok(Color::RGBA(channel(0)?, channel(2)?, channel(4)?, channel(6)?)),
+            _ => Err(format!("bad color: {}", value).into()),
+        }
     }
 
-    pub struct Relation {
-        ways: HashMap<i64, String>,
-        nodes: HashMap<i64, String>,
-        tags: HashMap<String, String>,
+    impl Default for StyleSheet {
+        fn default() -> Self {
+            let rule = |key: &str, value: Option<&str>, color: Color, width: u8, fill: Option<Color>| StyleRule {
+                key: key.to_string(),
+                value: value.map(|value| value.to_string()),
+                style: Style { color, width, fill },
+            };
+
+            StyleSheet {
+                rules: vec![
+                    rule("highway", Some("motorway"), Color::RGB(226, 122, 143), 5, None),
+                    rule("highway", Some("primary"), Color::RGB(252, 214, 164), 4, None),
+                    rule("highway", Some("secondary"), Color::RGB(246, 250, 187), 4, None),
+                    rule("highway", Some("footway"), Color::RGB(250, 128, 114), 1, None),
+                    rule("highway", Some("steps"), Color::RGB(250, 128, 114), 2, None),
+                    rule("highway", None, Color::RGB(160, 160, 160), 3, None),
+                    rule("railway", None, Color::RGB(110, 110, 110), 2, None),
+                    rule("waterway", None, Color::RGB(170, 211, 223), 2, None),
+                    rule("building", None, Color::RGB(196, 182, 171), 1, Some(Color::RGB(217, 208, 201))),
+                    rule("natural", Some("water"), Color::RGB(170, 211, 223), 1, Some(Color::RGB(170, 211, 223))),
+                    rule("leisure", Some("park"), Color::RGB(173, 209, 158), 1, Some(Color::RGB(200, 250, 204))),
+                    rule("landuse", None, Color::RGB(200, 200, 190), 1, Some(Color::RGB(235, 235, 225))),
+                ],
+                fallback: Style { color: Color::RGB(200, 200, 200), width: 1, fill: None },
+                point: Style { color: Color::RGB(90, 90, 200), width: 3, fill: None },
+            }
+        }
     }
+}
 
-    impl Relation {
-        pub async fn get_nodes(output_format: OutputFormat, min_lat: f32, min_lon: f32, max_lat: f32, max_lon: f32) -> Result<HashMap<i64, Relation>, Box<dyn Error>> {
-            let body = Relation::build_query(&output_format, min_lat, min_lon, max_lat, max_lon);
-            let response_body = match post("https://overpass-api.de/api/interpreter", body).await {
-                Ok(response) => response,
-                Err(err) => {
-                    eprintln!("Error: {:?}", err);
-                    return Err(err);
+pub mod map {
+    use std::borrow::Cow;
+
+    use sdl2::gfx::primitives::DrawRenderer;
+    use sdl2::render::{Canvas, RenderTarget};
+
+    use crate::camera::Camera;
+    use crate::dataset::OsmDataset;
+    use crate::geo::BBox;
+    use crate::geojson::multipolygon;
+    use crate::lod::LevelOfDetail;
+    use crate::osm::Relation;
+    use crate::style::StyleSheet;
+
+    /// how far past the viewport edges, in pixels, ways and points are still drawn so thick lines are not cut off at tile seams
+    pub const CULL_MARGIN: i32 = 16;
+
+    /// ways come from the level of detail matching the camera zoom, ways it left out are skipped,
+    /// and only what reaches into the viewport is drawn
+    ///
+    /// multipolygon relations are filled with their own style, holes left empty, and stand in for their untagged rings
+    pub fn draw_map<T: RenderTarget>(canvas: &mut Canvas<T>, camera: &Camera, dataset: &OsmDataset, lod: &LevelOfDetail, style_sheet: &StyleSheet) {
+        let level = lod.level(camera.zoom);
+        let top_left = camera.unproject(-CULL_MARGIN, -CULL_MARGIN);
+        let bottom_right = camera.unproject(camera.viewport_width as i32 + CULL_MARGIN, camera.viewport_height as i32 + CULL_MARGIN);
+        let view = BBox::new(bottom_right.lat, top_left.lon, top_left.lat, bottom_right.lon);
+        let is_multipolygon = |relation: &Relation| relation.tags.get("type").map(|value| value.as_str()) == Some("multipolygon");
+
+        for relation in dataset.relations().values().filter(|relation| is_multipolygon(relation)) {
+            if let Some(level) = level {
+                if !relation.ways().any(|member| level.ways.contains_key(&member.id)) { continue; }
+            }
+
+            let style = style_sheet.style_for(&relation.tags);
+            for polygon in multipolygon(relation, dataset) {
+                if !BBox::from_points(&polygon[0]).is_some_and(|bounds| bounds.intersects(&view)) { continue; }
+
+                let rings: Vec<Vec<(i32, i32)>> = polygon.iter()
+                    .map(|ring| ring.iter().map(|point| camera.project(*point)).collect())
+                    .collect();
+                if let Some(fill) = style.fill {
+                    for (y, start, end) in even_odd_spans(&rings, camera.viewport_height as i32) {
+                        let _ = canvas.hline(clamp(start), clamp(end), clamp(y), fill);
+                    }
                 }
-            };
+                for ring in &rings {
+                    draw_polyline(canvas, ring, false, style);
+                }
+            }
+        }
 
-            let result = match output_format {
-                OutputFormat::JSON => Relation::parse_json(&response_body),
-                OutputFormat::XML => Relation::parse_xml(&response_body),
+        let visible = lod.way_bounds.iter().filter(|(_, bounds)| bounds.intersects(&view));
+        for way in visible.filter_map(|(id, _)| dataset.ways().get(id)) {
+            if way.tags.is_empty() && dataset.parent_relations("way", way.id).any(is_multipolygon) { continue; }
+
+            let style = style_sheet.style_for(&way.tags);
+            let coordinates = match level {
+                Some(level) => match level.ways.get(&way.id) {
+                    Some(coordinates) => Cow::Borrowed(coordinates),
+                    None => continue,
+                },
+                None => Cow::Owned(dataset.way_coordinates(way)),
             };
+            let points: Vec<(i32, i32)> = coordinates.iter()
+                .map(|point| camera.project(*point))
+                .collect();
+
+            match (points.is_empty(), way.center) {
+                (true, Some(center)) => {
+                    let (x, y) = camera.project(center);
+                    let _ = canvas.filled_circle(clamp(x), clamp(y), style.width as i16 + 1, style.color);
+                }
+                _ => draw_polyline(canvas, &points, way.is_closed(), style),
+            }
+        }
+
+        for node in dataset.nodes().values() {
+            if node.tags.is_empty() || !view.contains(node.location()) { continue; }
 
-            Ok(result)
+            let (x, y) = camera.project(node.location());
+            let _ = canvas.filled_circle(clamp(x), clamp(y), style_sheet.point.width as i16, style_sheet.point.color);
         }
+    }
+
+    pub fn draw_polyline<T: RenderTarget>(canvas: &mut Canvas<T>, points: &[(i32, i32)], closed: bool, style: &crate::style::Style) {
+        if points.len() < 2 { return; }
 
-        fn build_query (output_format: &OutputFormat, min_lat: f32, min_lon: f32, max_lat: f32, max_lon: f32) -> String {
-            format!("[out:{}]; relation({}, {}, {}, {}); out;", get_output_format(&output_format), min_lat, min_lon, max_lat, max_lon)
+        if let (true, Some(fill)) = (closed, style.fill) {
+            let xs: Vec<i16> = points.iter().map(|(x, _)| clamp(*x)).collect();
+            let ys: Vec<i16> = points.iter().map(|(_, y)| clamp(*y)).collect();
+            let _ = canvas.filled_polygon(&xs, &ys, fill);
         }
 
-        fn parse_json (string: &str) -> HashMap<i64, Relation> {
-            let result: JsonRelationRoot = serde_json::from_str(string).unwrap();
-            let mut deserialized_relations: Vec<JsonRelation> = Vec::new();
-            let mut relations: HashMap<i64, Relation> = HashMap::new();
+        for segment in points.windows(2) {
+            let (x1, y1) = segment[0];
+            let (x2, y2) = segment[1];
 
-            for element in result.elements {
-                let mut members: Vec<RelationMember> = Vec::new();
-                if let mut element_members = element.members {
-                    for mut member in element_members {
-                        members.push( RelationMember { id: member.id, element_type: member.element_type, role: member.role } );
-                    }
+            let _ = if style.width > 1 {
+                canvas.thick_line(clamp(x1), clamp(y1), clamp(x2), clamp(y2), style.width, style.color)
+            } else {
+                canvas.line(clamp(x1), clamp(y1), clamp(x2), clamp(y2), style.color)
+            };
+        }
+    }
+
+    /// the pixel runs inside `rings` on each of the `height` rows, as (y, first x, last x), by the even-odd rule so inner rings stay empty
+    pub fn even_odd_spans(rings: &[Vec<(i32, i32)>], height: i32) -> Vec<(i32, i32, i32)> {
+        let mut spans = Vec::new();
+        let points = rings.iter().flatten();
+        let (Some(top), Some(bottom)) = (points.clone().map(|(_, y)| *y).min(), points.map(|(_, y)| *y).max()) else { return spans };
+
+        for y in top.max(0)..bottom.min(height) {
+            // sampled through the middle of the row so vertices never sit exactly on it
+            let row = y as f64 + 0.5;
+            let mut crossings: Vec<f64> = rings.iter()
+                .flat_map(|ring| ring.windows(2))
+                .filter(|edge| (edge[0].1 as f64 > row) != (edge[1].1 as f64 > row))
+                .map(|edge| {
+                    let ((x1, y1), (x2, y2)) = (edge[0], edge[1]);
+                    x1 as f64 + (row - y1 as f64) * (x2 - x1) as f64 / (y2 - y1) as f64
+                })
+                .collect();
+            crossings.sort_by(f64::total_cmp);
+
+            for pair in crossings.chunks_exact(2) {
+                let (start, end) = (pair[0].round() as i32, pair[1].round() as i32 - 1);
+                if end >= start {
+                    spans.push((y, start, end));
                 }
+            }
+        }
 
-                let mut tags: HashMap<String, String> = HashMap::new();
-                if let mut element_tags = element.tags {
-                    let mut temp_tags: HashMap<String, String> = HashMap::new();
-                    for (key, value) in element_tags {
-                        temp_tags.insert(key, value);
-                    }
-                    tags = temp_tags;
+        spans
+    }
+
+    /// gfx primitives take i16 coordinates, so far off-screen points are pinned to the edge of that range
+    pub fn clamp(value: i32) -> i16 {
+        value.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+    }
+}
+
+pub mod search {
+    use std::collections::{BTreeMap, HashMap};
+
+    use crate::dataset::OsmDataset;
+    use crate::geo::{polygon_centroid, LatLon};
+
+    const MAX_HITS: usize = 8;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum ElementRef {
+        Node(i64),
+        Way(i64),
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct SearchHit {
+        pub element: ElementRef,
+        pub label: String,
+        pub location: LatLon,
+        pub score: usize,
+    }
+
+    struct Entry {
+        element: ElementRef,
+        label: String,
+        location: LatLon,
+    }
+
+    /// prefix/fuzzy index over the `name` and `addr:*` tags of nodes and ways
+    pub struct SearchIndex {
+        entries: Vec<Entry>,
+        tokens: BTreeMap<String, Vec<usize>>,
+    }
+
+    impl SearchIndex {
+        pub fn build(dataset: &OsmDataset) -> SearchIndex {
+            let mut index = SearchIndex { entries: Vec::new(), tokens: BTreeMap::new() };
+
+            for (id, node) in dataset.nodes() {
+                index.add(ElementRef::Node(*id), &node.tags, node.location());
+            }
+
+            for (id, way) in dataset.ways() {
+                let coords = dataset.way_coordinates(way);
+                let location = if way.is_closed() {
+                    polygon_centroid(&coords)
+                } else {
+                    coords.get(coords.len() / 2).copied()
+                };
+
+                if let Some(location) = location.or(way.center) {
+                    index.add(ElementRef::Way(*id), &way.tags, location);
                 }
+            }
+
+            index
+        }
+
+        fn add(&mut self, element: ElementRef, tags: &HashMap<String, String>, location: LatLon) {
+            let label = match label(tags) {
+                Some(label) => label,
+                None => return,
+            };
+
+            let entry_index = self.entries.len();
+            let searchable = tags.iter()
+                .filter(|(key, _)| key.as_str() == "name" || key.starts_with("addr:"))
+                .map(|(_, value)| value.as_str());
 
-                deserialized_relations.push(
-                    JsonRelation {
-                        id: element.id,
-                        members,
-                        tags,
-                        element_type: "relation".to_string(),
+            for value in searchable {
+                for token in tokenize(value) {
+                    let postings = self.tokens.entry(token).or_default();
+                    if postings.last() != Some(&entry_index) {
+                        postings.push(entry_index);
                     }
-                );
+                }
             }
 
-            for d_relation in deserialized_relations {
-                let mut ways: HashMap<i64, String> = HashMap::new();
-                let mut nodes: HashMap<i64, String> = HashMap::new();
-                let mut tags: HashMap<String, String> = HashMap::new();
+            self.entries.push(Entry { element, label, location });
+        }
 
-                for member in d_relation.members {
-                    if member.element_type == "node" {
-                        nodes.insert(member.id, member.role);
-                    }
-                    else if member.element_type == "way" {
-                        ways.insert(member.id, member.role);
+        /// every query word has to match a word of the entry, either as a prefix or within a small edit distance
+        pub fn search(&self, query: &str) -> Vec<SearchHit> {
+            let query_tokens = tokenize(query);
+            if query_tokens.is_empty() { return Vec::new(); }
+
+            let mut scores: HashMap<usize, usize> = HashMap::new();
+            for (i, query_token) in query_tokens.iter().enumerate() {
+                let mut token_scores: HashMap<usize, usize> = HashMap::new();
+
+                for (token, postings) in self.tokens.range(query_token.clone()..) {
+                    if !token.starts_with(query_token.as_str()) { break; }
+                    let score = if token == query_token { 0 } else { 1 };
+                    for entry in postings {
+                        let best = token_scores.entry(*entry).or_insert(score);
+                        *best = (*best).min(score);
                     }
                 }
 
-                for (key, value) in d_relation.tags {
-                    tags.insert(key, value);
+                let max_distance = if query_token.chars().count() > 5 { 2 } else { 1 };
+                for (token, postings) in &self.tokens {
+                    let distance = edit_distance(query_token, token);
+                    if distance == 0 || distance > max_distance { continue; }
+                    for entry in postings {
+                        let best = token_scores.entry(*entry).or_insert(distance + 1);
+                        *best = (*best).min(distance + 1);
+                    }
                 }
 
-                relations.insert(d_relation.id, Relation { ways, nodes, tags });
+                if i == 0 {
+                    scores = token_scores;
+                } else {
+                    scores = scores.into_iter()
+                        .filter_map(|(entry, score)| token_scores.get(&entry).map(|token_score| (entry, score + token_score)))
+                        .collect();
+                }
             }
 
-            relations
+            let mut hits: Vec<SearchHit> = scores.into_iter()
+                .map(|(entry_index, score)| {
+                    let entry = &self.entries[entry_index];
+                    SearchHit { element: entry.element, label: entry.label.clone(), location: entry.location, score }
+                })
+                .collect();
+            hits.sort_by(|a, b| a.score.cmp(&b.score).then_with(|| a.label.cmp(&b.label)));
+            hits.truncate(MAX_HITS);
+
+            hits
         }
-        
-        fn parse_xml (string: &str) -> HashMap<i64, Relation> {
-            let result: XmlRelationRoot = serde_xml_rs::from_str(string).unwrap();
-            let mut relations: HashMap<i64, Relation> = HashMap::new();
-            let mut deserialized_relations: Vec<XmlRelation> = Vec::new();
-            
-            for relation in result.relations {
-                let mut members: Vec<RelationMember> = Vec::new();
-                for member in relation.members {
-                    members.push(member);
-                }
+    }
 
-                let mut tags: Vec<XmlTag> = Vec::new();
-                for tag in relation.tags {
-                    tags.push(XmlTag { key: tag.key, value: tag.value });
-                }
+    fn label(tags: &HashMap<String, String>) -> Option<String> {
+        if let Some(name) = tags.get("name") {
+            return Some(name.clone());
+        }
 
-                deserialized_relations.push(  XmlRelation { id: relation.id, members, tags })
-            }
+        let street = tags.get("addr:street");
+        let number = tags.get("addr:housenumber");
+        match (street, number) {
+            (Some(street), Some(number)) => Some(format!("{} {}", street, number)),
+            (Some(street), None) => Some(street.clone()),
+            _ => tags.iter().find(|(key, _)| key.starts_with("addr:")).map(|(_, value)| value.clone()),
+        }
+    }
 
-            for d_relation in deserialized_relations {
-                let mut nodes: HashMap<i64, String> = HashMap::new();
-                let mut ways: HashMap<i64, String> = HashMap::new();
-                for member in d_relation.members {
-                    if member.element_type == "node" {
-                        nodes.insert(member.id, member.role);
-                    }
-                    else if member.element_type == "way" {
-                        ways.insert(member.id, member.role);
-                    }
-                }
+    fn tokenize(value: &str) -> Vec<String> {
+        value.split(|c: char| !c.is_alphanumeric())
+            .filter(|token| !token.is_empty())
+            .map(|token| token.to_lowercase())
+            .collect()
+    }
 
-                let mut tags: HashMap<String, String> = HashMap::new();
-                for tag in d_relation.tags {
-                    tags.insert(tag.key, tag.value);
-                }
+    fn edit_distance(a: &str, b: &str) -> usize {
+        let a: Vec<char> = a.chars().collect();
+        let b: Vec<char> = b.chars().collect();
+        let mut previous: Vec<usize> = (0..=b.len()).collect();
 
-                relations.insert(d_relation.id, Relation { nodes, ways, tags } );
+        for i in 1..=a.len() {
+            let mut current = vec![i; b.len() + 1];
+            for j in 1..=b.len() {
+                let substitution = previous[j - 1] + if a[i - 1] == b[j - 1] { 0 } else { 1 };
+                current[j] = substitution.min(previous[j] + 1).min(current[j - 1] + 1);
             }
-            
-            relations
+            previous = current;
         }
+
+        previous[b.len()]
     }
 
-    pub enum OutputFormat {
-        JSON,
-        XML
+    /// state of the in-window search box
+    #[derive(Default)]
+    pub struct SearchBox {
+        pub active: bool,
+        pub query: String,
+        pub hits: Vec<SearchHit>,
+        pub selected: usize,
     }
 
-    fn get_output_format (format: &OutputFormat) -> String {
-        match format {
-            OutputFormat::JSON => "json".to_string(),
-            OutputFormat::XML => "xml".to_string(),
+    impl SearchBox {
+        pub fn new() -> SearchBox {
+            SearchBox::default()
+        }
+
+        pub fn open(&mut self) {
+            self.active = true;
+            self.query.clear();
+            self.hits.clear();
+            self.selected = 0;
+        }
+
+        pub fn close(&mut self) {
+            self.active = false;
+        }
+
+        pub fn push_str(&mut self, text: &str, index: &SearchIndex) {
+            self.query.push_str(text);
+            self.refresh(index);
         }
+
+        pub fn backspace(&mut self, index: &SearchIndex) {
+            self.query.pop();
+            self.refresh(index);
+        }
+
+        pub fn select_next(&mut self) {
+            if !self.hits.is_empty() {
+                self.selected = (self.selected + 1) % self.hits.len();
+            }
+        }
+
+        pub fn selected_hit(&self) -> Option<&SearchHit> {
+            self.hits.get(self.selected)
+        }
+
+        fn refresh(&mut self, index: &SearchIndex) {
+            self.hits = index.search(&self.query);
+            self.selected = 0;
+        }
+    }
+}
+
+pub mod cli {
+    use std::collections::HashMap;
+    use std::error::Error;
+
+    use crate::geo::BBox;
+
+    /// `--key value` pairs and bare `--flag`s following a command name
+    pub struct Options {
+        values: HashMap<String, String>,
     }
-}
\ No newline at end of file
+
+    impl Options {
+        pub fn parse(args: &[String]) -> Result<Options, Box<dyn Error>> {
+            let mut values = HashMap::new();
+            let mut i = 0;
+
+            while i < args.len() {
+                let key = match args[i].strip_prefix("--") {
+                    Some(key) => key.to_string(),
+                    None => return Err(format!("unexpected argument: {}", args[i]).into()),
+                };
+
+                match args.get(i + 1) {
+                    Some(value) if !value.starts_with("--") => {
+                        values.insert(key, value.clone());
+                        i += 2;
+                    }
+                    _ => {
+                        values.insert(key, String::new());
+                        i += 1;
+                    }
+                }
+            }
+
+            Ok(Options { values })
+        }
+
+        pub fn get(&self, key: &str) -> Option<&str> {
+            self.values.get(key).map(|value| value.as_str())
+        }
+
+        pub fn flag(&self, key: &str) -> bool {
+            self.values.contains_key(key)
+        }
+
+        /// fills in `key` unless the command line gave it
+        pub fn set_default(&mut self, key: &str, value: &str) {
+            self.values.entry(key.to_string()).or_insert_with(|| value.to_string());
+        }
+
+        pub fn require(&self, key: &str) -> Result<&str, Box<dyn Error>> {
+            self.get(key).ok_or_else(|| format!("missing --{}", key).into())
+        }
+
+        /// `min_lat,min_lon,max_lat,max_lon`, the same order overpass uses
+        pub fn bbox(&self, key: &str) -> Result<BBox, Box<dyn Error>> {
+            BBox::parse(self.require(key)?)
+        }
+
+        /// `800x600`
+        pub fn size(&self, key: &str, default: (u32, u32)) -> Result<(u32, u32), Box<dyn Error>> {
+            let value = match self.get(key) {
+                Some(value) => value,
+                None => return Ok(default),
+            };
+
+            match value.split_once('x') {
+                Some((width, height)) => Ok((width.parse()?, height.parse()?)),
+                None => Err(format!("--{} needs the form WIDTHxHEIGHT", key).into()),
+            }
+        }
+    }
+}
+
+pub mod snapshot {
+    use std::error::Error;
+    use std::fs::File;
+    use std::io::BufWriter;
+    use std::path::Path;
+
+    use sdl2::pixels::{Color, PixelFormatEnum};
+    use sdl2::render::{Canvas, RenderTarget};
+    use sdl2::surface::Surface;
+
+    use crate::camera::Camera;
+    use crate::cli::Options;
+    use crate::lod::LevelOfDetail;
+    use crate::map::draw_map;
+    use crate::osm::GeometryMode;
+    use crate::source;
+    use crate::style::StyleSheet;
+
+    pub enum ImageFormat {
+        BMP,
+        PNG,
+    }
+
+    impl ImageFormat {
+        pub fn from_path(path: &str) -> Result<ImageFormat, Box<dyn Error>> {
+            let extension = Path::new(path).extension()
+                .and_then(|extension| extension.to_str())
+                .map(|extension| extension.to_lowercase());
+
+            match extension.as_deref() {
+                Some("bmp") => Ok(ImageFormat::BMP),
+                Some("png") => Ok(ImageFormat::PNG),
+                _ => Err(format!("unsupported image format: {}", path).into()),
+            }
+        }
+    }
+
+    /// reads back whatever is currently on the canvas, so call it after drawing and before `present`
+    pub fn save_canvas<T: RenderTarget>(canvas: &Canvas<T>, path: &str) -> Result<(), Box<dyn Error>> {
+        let format = ImageFormat::from_path(path)?;
+        let (width, height) = canvas.output_size()?;
+        let mut pixels = canvas.read_pixels(None, PixelFormatEnum::RGB24)?;
+
+        match format {
+            ImageFormat::BMP => {
+                let surface = Surface::from_data(&mut pixels, width, height, width * 3, PixelFormatEnum::RGB24)?;
+                surface.save_bmp(path)?;
+            }
+            ImageFormat::PNG => {
+                let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.write_header()?.write_image_data(&pixels)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn screenshot_path() -> String {
+        let seconds = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        format!("screenshot-{}.png", seconds)
+    }
+
+    /// `render (--bbox min_lat,min_lon,max_lat,max_lon | --file data.osm) [--size 800x600] [--out map.png]`
+    ///
+    /// draws into a software surface, so no window or display is needed
+    #[tokio::main]
+    pub async fn render(args: &[String]) -> Result<(), Box<dyn Error>> {
+        let options = Options::parse(args)?;
+        let (width, height) = options.size("size", (800, 600))?;
+        let path = options.get("out").unwrap_or("map.png");
+        ImageFormat::from_path(path)?;
+
+        let (dataset, boundary) = source::load(&options, GeometryMode::Geom).await?;
+        let bbox = match (&boundary, options.get("bbox")) {
+            (Some(boundary), _) => boundary.bbox,
+            (None, Some(_)) => options.bbox("bbox")?,
+            (None, None) => dataset.bbox().ok_or("nothing to render")?,
+        };
+
+        let camera = Camera::fit_bbox(&bbox, width, height);
+        let mut canvas = Surface::new(width, height, PixelFormatEnum::RGB888)?.into_canvas()?;
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        canvas.clear();
+        draw_map(&mut canvas, &camera, &dataset, &LevelOfDetail::build(&dataset), &StyleSheet::default());
+        if let Some(boundary) = &boundary {
+            boundary.draw_mask(&mut canvas, &camera, Color::RGB(255, 255, 255));
+        }
+
+        save_canvas(&canvas, path)?;
+        println!("saved {}x{} map of {} to {}", width, height, bbox, path);
+
+        Ok(())
+    }
+}
+
+pub mod geojson {
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::fs::File;
+    use std::io::BufWriter;
+
+    use serde_json::{json, Map, Value};
+
+    use crate::cli::Options;
+    use crate::dataset::OsmDataset;
+    use crate::geo::{point_in_polygon, LatLon};
+    use crate::osm::{GeometryMode, Relation, TagFilter};
+    use crate::source;
+
+    pub struct GeoJsonOptions {
+        /// an element is exported when it matches any of these, or always when empty
+        pub filters: Vec<TagFilter>,
+        pub include_untagged: bool,
+    }
+
+    impl GeoJsonOptions {
+        fn accepts(&self, tags: &HashMap<String, String>) -> bool {
+            if tags.is_empty() {
+                return self.include_untagged;
+            }
+
+            self.filters.is_empty() || self.filters.iter().any(|filter| filter.matches(tags))
+        }
+    }
+
+    pub fn to_feature_collection(dataset: &OsmDataset, options: &GeoJsonOptions) -> Value {
+        let mut features: Vec<Value> = Vec::new();
+
+        let mut node_ids: Vec<&i64> = dataset.nodes().keys().collect();
+        node_ids.sort();
+        for id in node_ids {
+            let node = &dataset.nodes()[id];
+            if !options.accepts(&node.tags) { continue; }
+
+            let geometry = json!({ "type": "Point", "coordinates": position(&node.location()) });
+            features.push(feature("node", *id, geometry, &node.tags));
+        }
+
+        let mut way_ids: Vec<&i64> = dataset.ways().keys().collect();
+        way_ids.sort();
+        for id in way_ids {
+            let way = &dataset.ways()[id];
+            if !options.accepts(&way.tags) { continue; }
+
+            let coordinates: Vec<Value> = dataset.way_coordinates(way).iter()
+                .map(position)
+                .collect();
+            if coordinates.len() < 2 { continue; }
+
+            let geometry = if way.is_area() && coordinates.len() >= 4 && coordinates.first() == coordinates.last() {
+                json!({ "type": "Polygon", "coordinates": [coordinates] })
+            } else {
+                json!({ "type": "LineString", "coordinates": coordinates })
+            };
+            features.push(feature("way", *id, geometry, &way.tags));
+        }
+
+        let mut relation_ids: Vec<&i64> = dataset.relations().keys().collect();
+        relation_ids.sort();
+        for id in relation_ids {
+            let relation = &dataset.relations()[id];
+            if relation.tags.get("type").map(|value| value.as_str()) != Some("multipolygon") { continue; }
+            if !options.accepts(&relation.tags) { continue; }
+
+            let polygons = multipolygon(relation, dataset);
+            if polygons.is_empty() { continue; }
+
+            let coordinates: Vec<Value> = polygons.iter()
+                .map(|rings| Value::Array(rings.iter()
+                    .map(|ring| Value::Array(ring.iter().map(position).collect()))
+                    .collect()))
+                .collect();
+            let geometry = json!({ "type": "MultiPolygon", "coordinates": coordinates });
+            features.push(feature("relation", *id, geometry, &relation.tags));
+        }
+
+        json!({ "type": "FeatureCollection", "features": features })
+    }
+
+    fn position(point: &LatLon) -> Value {
+        json!([point.lon, point.lat])
+    }
+
+    fn feature(element_type: &str, id: i64, geometry: Value, tags: &HashMap<String, String>) -> Value {
+        let mut properties = Map::new();
+        for (key, value) in tags {
+            properties.insert(key.clone(), Value::String(value.clone()));
+        }
+
+        json!({
+            "type": "Feature",
+            "id": format!("{}/{}", element_type, id),
+            "geometry": geometry,
+            "properties": properties,
+        })
+    }
+
+    /// outer rings with the inner rings that fall inside them, as lat/lon pairs
+    pub fn multipolygon(relation: &Relation, dataset: &OsmDataset) -> Vec<Vec<Vec<LatLon>>> {
+        let role_segments = |role: &str| -> Vec<Vec<LatLon>> {
+            relation.ways()
+                .filter(|member| member.role == role || (role == "outer" && member.role.is_empty()))
+                .map(|member| match (&member.geometry, dataset.way(member.id)) {
+                    (Some(geometry), _) => geometry.clone(),
+                    (None, Some(way)) => dataset.way_coordinates(way),
+                    (None, None) => Vec::new(),
+                })
+                .collect()
+        };
+
+        let outers = assemble_rings(role_segments("outer"));
+        let inners = assemble_rings(role_segments("inner"));
+
+        let mut polygons: Vec<Vec<Vec<LatLon>>> = outers.into_iter().map(|outer| vec![outer]).collect();
+        for inner in inners {
+            let owner = polygons.iter_mut().find(|polygon| point_in_polygon(inner[0], &polygon[0]));
+            if let Some(polygon) = owner {
+                polygon.push(inner);
+            }
+        }
+
+        polygons
+    }
+
+    /// joins way segments end to end into closed rings, dropping whatever cannot be closed
+    ///
+    /// segments are matched on identical end coordinates, which holds for shared nodes
+    /// whether the points came from node lookups or from inline `out geom` geometry
+    pub fn assemble_rings(segments: Vec<Vec<LatLon>>) -> Vec<Vec<LatLon>> {
+        let mut segments: Vec<Vec<LatLon>> = segments.into_iter()
+            .filter(|segment| segment.len() >= 2)
+            .collect();
+        let mut rings: Vec<Vec<LatLon>> = Vec::new();
+
+        while let Some(mut ring) = segments.pop() {
+            while ring.first() != ring.last() {
+                let end = *ring.last().unwrap();
+                let next = segments.iter().position(|segment| segment.first() == Some(&end) || segment.last() == Some(&end));
+
+                match next {
+                    Some(index) => {
+                        let mut segment = segments.remove(index);
+                        if segment.first() != Some(&end) {
+                            segment.reverse();
+                        }
+                        ring.extend(segment.into_iter().skip(1));
+                    }
+                    None => break,
+                }
+            }
+
+            if ring.len() >= 4 && ring.first() == ring.last() {
+                rings.push(ring);
+            }
+        }
+
+        rings
+    }
+
+    /// `geojson (--bbox min_lat,min_lon,max_lat,max_lon | --file data.osm) [--out data.geojson] [--filter amenity=cafe,shop=*] [--untagged]`
+    #[tokio::main]
+    pub async fn export(args: &[String]) -> Result<(), Box<dyn Error>> {
+        let options = Options::parse(args)?;
+        let path = options.get("out").unwrap_or("data.geojson");
+        let geojson_options = GeoJsonOptions {
+            filters: TagFilter::parse_list(options.get("filter").unwrap_or("")),
+            include_untagged: options.flag("untagged"),
+        };
+
+        let (dataset, _) = source::load(&options, GeometryMode::Geom).await?;
+        let collection = to_feature_collection(&dataset, &geojson_options);
+        serde_json::to_writer(BufWriter::new(File::create(path)?), &collection)?;
+        println!("saved {} features to {}", collection["features"].as_array().map(|features| features.len()).unwrap_or(0), path);
+
+        Ok(())
+    }
+}
+
+pub mod osm_xml {
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::fs::File;
+    use std::io::{self, BufWriter, Write};
+
+    use crate::cli::Options;
+    use crate::dataset::OsmDataset;
+    use crate::osm::{GeometryMode, Metadata};
+    use crate::source;
+
+    /// writes an `<osm version="0.6">` document with nodes, ways and relations each sorted by id
+    pub fn write_osm<W: Write>(writer: &mut W, dataset: &OsmDataset) -> io::Result<()> {
+        writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(writer, "<osm version=\"0.6\" generator=\"sdl-rust\">")?;
+
+        for id in sorted_ids(dataset.nodes()) {
+            let node = &dataset.nodes()[&id];
+            write!(writer, "  <node id=\"{}\"{} lat=\"{}\" lon=\"{}\"", id, meta_attributes(&node.meta), node.lat, node.lon)?;
+            if node.tags.is_empty() {
+                writeln!(writer, "/>")?;
+            } else {
+                writeln!(writer, ">")?;
+                write_tags(writer, &node.tags)?;
+                writeln!(writer, "  </node>")?;
+            }
+        }
+
+        for id in sorted_ids(dataset.ways()) {
+            let way = &dataset.ways()[&id];
+            writeln!(writer, "  <way id=\"{}\"{}>", id, meta_attributes(&way.meta))?;
+            for node_id in &way.nodes {
+                writeln!(writer, "    <nd ref=\"{}\"/>", node_id)?;
+            }
+            write_tags(writer, &way.tags)?;
+            writeln!(writer, "  </way>")?;
+        }
+
+        for id in sorted_ids(dataset.relations()) {
+            let relation = &dataset.relations()[&id];
+            writeln!(writer, "  <relation id=\"{}\"{}>", id, meta_attributes(&relation.meta))?;
+            for member in &relation.members {
+                writeln!(writer, "    <member type=\"{}\" ref=\"{}\" role=\"{}\"/>", escape(&member.element_type), member.id, escape(&member.role))?;
+            }
+            write_tags(writer, &relation.tags)?;
+            writeln!(writer, "  </relation>")?;
+        }
+
+        writeln!(writer, "</osm>")?;
+        writer.flush()
+    }
+
+    /// attributes in the order the osm api writes them, skipping whatever the query did not return
+    fn meta_attributes(meta: &Metadata) -> String {
+        let mut attributes = String::new();
+        if let Some(version) = meta.version {
+            attributes.push_str(&format!(" version=\"{}\"", version));
+        }
+        if let Some(timestamp) = &meta.timestamp {
+            attributes.push_str(&format!(" timestamp=\"{}\"", escape(timestamp)));
+        }
+        if let Some(changeset) = meta.changeset {
+            attributes.push_str(&format!(" changeset=\"{}\"", changeset));
+        }
+        if let Some(uid) = meta.uid {
+            attributes.push_str(&format!(" uid=\"{}\"", uid));
+        }
+        if let Some(user) = &meta.user {
+            attributes.push_str(&format!(" user=\"{}\"", escape(user)));
+        }
+        attributes
+    }
+
+    fn sorted_ids<T>(elements: &HashMap<i64, T>) -> Vec<i64> {
+        let mut ids: Vec<i64> = elements.keys().copied().collect();
+        ids.sort();
+        ids
+    }
+
+    /// tags sorted by key, so the same data always produces the same file
+    fn write_tags<W: Write>(writer: &mut W, tags: &HashMap<String, String>) -> io::Result<()> {
+        let mut keys: Vec<&String> = tags.keys().collect();
+        keys.sort();
+
+        for key in keys {
+            writeln!(writer, "    <tag k=\"{}\" v=\"{}\"/>", escape(key), escape(&tags[key]))?;
+        }
+
+        Ok(())
+    }
+
+    fn escape(value: &str) -> String {
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&apos;"),
+                '\n' => escaped.push_str("&#10;"),
+                '\r' => escaped.push_str("&#13;"),
+                '\t' => escaped.push_str("&#9;"),
+                _ => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    /// `osm (--bbox min_lat,min_lon,max_lat,max_lon | --file data.osm [--osc changes.osc]) [--out data.osm]`
+    #[tokio::main]
+    pub async fn export(args: &[String]) -> Result<(), Box<dyn Error>> {
+        let options = Options::parse(args)?;
+        let path = options.get("out").unwrap_or("data.osm");
+
+        let (dataset, _) = source::load(&options, GeometryMode::None).await?;
+
+        write_osm(&mut BufWriter::new(File::create(path)?), &dataset)?;
+        println!("saved {} nodes, {} ways and {} relations to {}", dataset.nodes().len(), dataset.ways().len(), dataset.relations().len(), path);
+
+        Ok(())
+    }
+}
+
+pub mod geo {
+    use std::error::Error;
+    use std::f64::consts::PI;
+    use std::fmt;
+
+    use serde::Deserialize;
+
+    /// mean earth radius in meters
+    pub const EARTH_RADIUS: f64 = 6_371_008.8;
+
+    #[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+    pub struct LatLon {
+        pub lat: f64,
+        pub lon: f64,
+    }
+
+    impl LatLon {
+        pub fn new(lat: f64, lon: f64) -> LatLon {
+            LatLon { lat, lon }
+        }
+
+        /// great circle distance in meters
+        pub fn distance_to(&self, other: &LatLon) -> f64 {
+            haversine_distance(*self, *other)
+        }
+
+        /// initial bearing in degrees clockwise from north
+        pub fn bearing_to(&self, other: &LatLon) -> f64 {
+            bearing(*self, *other)
+        }
+    }
+
+    /// overpass order: south, west, north, east
+    #[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+    pub struct BBox {
+        #[serde(rename = "minlat")]
+        pub min_lat: f64,
+        #[serde(rename = "minlon")]
+        pub min_lon: f64,
+        #[serde(rename = "maxlat")]
+        pub max_lat: f64,
+        #[serde(rename = "maxlon")]
+        pub max_lon: f64,
+    }
+
+    impl BBox {
+        pub fn new(min_lat: f64, min_lon: f64, max_lat: f64, max_lon: f64) -> BBox {
+            BBox { min_lat, min_lon, max_lat, max_lon }
+        }
+
+        pub fn from_points<'a, I: IntoIterator<Item = &'a LatLon>>(points: I) -> Option<BBox> {
+            let mut points = points.into_iter();
+            let first = points.next()?;
+            let mut bbox = BBox::new(first.lat, first.lon, first.lat, first.lon);
+            for point in points {
+                bbox.extend(*point);
+            }
+            Some(bbox)
+        }
+
+        /// `min_lat,min_lon,max_lat,max_lon`
+        pub fn parse(value: &str) -> Result<BBox, Box<dyn Error>> {
+            let parts = value.split(',')
+                .map(|part| part.trim().parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()?;
+
+            match parts.as_slice() {
+                [min_lat, min_lon, max_lat, max_lon] => Ok(BBox::new(*min_lat, *min_lon, *max_lat, *max_lon)),
+                _ => Err(format!("bbox needs four comma separated values: {}", value).into()),
+            }
+        }
+
+        pub fn extend(&mut self, point: LatLon) {
+            self.min_lat = self.min_lat.min(point.lat);
+            self.min_lon = self.min_lon.min(point.lon);
+            self.max_lat = self.max_lat.max(point.lat);
+            self.max_lon = self.max_lon.max(point.lon);
+        }
+
+        pub fn contains(&self, point: LatLon) -> bool {
+            point.lat >= self.min_lat && point.lat <= self.max_lat &&
+            point.lon >= self.min_lon && point.lon <= self.max_lon
+        }
+
+        pub fn intersects(&self, other: &BBox) -> bool {
+            self.min_lat <= other.max_lat && self.max_lat >= other.min_lat &&
+            self.min_lon <= other.max_lon && self.max_lon >= other.min_lon
+        }
+
+        pub fn center(&self) -> LatLon {
+            LatLon::new((self.min_lat + self.max_lat) / 2.0, (self.min_lon + self.max_lon) / 2.0)
+        }
+    }
+
+    impl fmt::Display for BBox {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{},{},{},{}", self.min_lat, self.min_lon, self.max_lat, self.max_lon)
+        }
+    }
+
+    /// maps lat/lon onto a world plane normalized to 0..1 horizontally, with y growing southwards
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Projection {
+        WebMercator,
+        /// plate carrée, the world is twice as wide as it is high
+        Equirectangular,
+    }
+
+    impl Projection {
+        pub fn to_world(&self, point: LatLon) -> (f64, f64) {
+            match self {
+                Projection::WebMercator => {
+                    let sin_lat = point.lat.to_radians().sin().clamp(-0.9999, 0.9999);
+                    let x = (point.lon + 180.0) / 360.0;
+                    let y = 0.5 - ((1.0 + sin_lat) / (1.0 - sin_lat)).ln() / (4.0 * PI);
+                    (x, y)
+                }
+                Projection::Equirectangular => ((point.lon + 180.0) / 360.0, (90.0 - point.lat) / 360.0),
+            }
+        }
+
+        pub fn from_world(&self, x: f64, y: f64) -> LatLon {
+            match self {
+                Projection::WebMercator => {
+                    LatLon::new((PI * (1.0 - 2.0 * y)).sinh().atan().to_degrees(), x * 360.0 - 180.0)
+                }
+                Projection::Equirectangular => LatLon::new(90.0 - y * 360.0, x * 360.0 - 180.0),
+            }
+        }
+    }
+
+    /// a web mercator tile, `y` counting down from the north like xyz tile servers do
+    #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+    pub struct TileId {
+        pub z: u8,
+        pub x: u32,
+        pub y: u32,
+    }
+
+    impl TileId {
+        /// every tile at `zoom` that overlaps `bbox`
+        pub fn covering(bbox: &BBox, zoom: u8) -> Vec<TileId> {
+            let tiles = 2f64.powi(zoom as i32);
+            let index = |value: f64| (value * tiles).floor().clamp(0.0, tiles - 1.0) as u32;
+            let (min_x, min_y) = Projection::WebMercator.to_world(LatLon::new(bbox.max_lat, bbox.min_lon));
+            let (max_x, max_y) = Projection::WebMercator.to_world(LatLon::new(bbox.min_lat, bbox.max_lon));
+
+            (index(min_y)..=index(max_y))
+                .flat_map(|y| (index(min_x)..=index(max_x)).map(move |x| TileId { z: zoom, x, y }))
+                .collect()
+        }
+
+        /// the point at `(x, y)` of `extent` units across the tile
+        pub fn location(&self, x: f64, y: f64, extent: f64) -> LatLon {
+            let tiles = 2f64.powi(self.z as i32);
+            Projection::WebMercator.from_world((self.x as f64 + x / extent) / tiles, (self.y as f64 + y / extent) / tiles)
+        }
+
+        pub fn parent(&self) -> Option<TileId> {
+            match self.z {
+                0 => None,
+                z => Some(TileId { z: z - 1, x: self.x / 2, y: self.y / 2 }),
+            }
+        }
+    }
+
+    pub fn haversine_distance(a: LatLon, b: LatLon) -> f64 {
+        let d_lat = (b.lat - a.lat).to_radians();
+        let d_lon = (b.lon - a.lon).to_radians();
+        let h = (d_lat / 2.0).sin().powi(2) + a.lat.to_radians().cos() * b.lat.to_radians().cos() * (d_lon / 2.0).sin().powi(2);
+
+        2.0 * EARTH_RADIUS * h.sqrt().asin()
+    }
+
+    pub fn bearing(a: LatLon, b: LatLon) -> f64 {
+        let (lat_a, lat_b) = (a.lat.to_radians(), b.lat.to_radians());
+        let d_lon = (b.lon - a.lon).to_radians();
+        let y = d_lon.sin() * lat_b.cos();
+        let x = lat_a.cos() * lat_b.sin() - lat_a.sin() * lat_b.cos() * d_lon.cos();
+
+        (y.atan2(x).to_degrees() + 360.0) % 360.0
+    }
+
+    pub fn polyline_length(points: &[LatLon]) -> f64 {
+        points.windows(2).map(|segment| haversine_distance(segment[0], segment[1])).sum()
+    }
+
+    /// meters from `point` to the closest point of segment `a`-`b`, in a local equirectangular plane around `point`
+    pub fn distance_to_segment(point: LatLon, a: LatLon, b: LatLon) -> f64 {
+        let scale = point.lat.to_radians().cos();
+        let local = |other: LatLon| ((other.lon - point.lon).to_radians() * scale * EARTH_RADIUS, (other.lat - point.lat).to_radians() * EARTH_RADIUS);
+        let ((ax, ay), (bx, by)) = (local(a), local(b));
+        let (dx, dy) = (bx - ax, by - ay);
+        let length = dx * dx + dy * dy;
+        let t = if length > 0.0 { (-(ax * dx + ay * dy) / length).clamp(0.0, 1.0) } else { 0.0 };
+
+        (ax + t * dx).hypot(ay + t * dy)
+    }
+
+    /// area in square meters on the sphere, open or closed rings both work
+    pub fn polygon_area(ring: &[LatLon]) -> f64 {
+        if ring.len() < 3 { return 0.0; }
+
+        let mut sum = 0.0;
+        for i in 0..ring.len() {
+            let a = ring[i];
+            let b = ring[(i + 1) % ring.len()];
+            sum += (b.lon - a.lon).to_radians() * (2.0 + a.lat.to_radians().sin() + b.lat.to_radians().sin());
+        }
+
+        (sum * EARTH_RADIUS * EARTH_RADIUS / 2.0).abs()
+    }
+
+    /// area weighted centroid, computed in a local equirectangular plane around the first point
+    pub fn polygon_centroid(ring: &[LatLon]) -> Option<LatLon> {
+        let origin = *ring.first()?;
+        let scale = origin.lat.to_radians().cos();
+        let local: Vec<(f64, f64)> = ring.iter()
+            .map(|point| ((point.lon - origin.lon) * scale, point.lat - origin.lat))
+            .collect();
+
+        let mut area = 0.0;
+        let mut x = 0.0;
+        let mut y = 0.0;
+        for i in 0..local.len() {
+            let (x0, y0) = local[i];
+            let (x1, y1) = local[(i + 1) % local.len()];
+            let cross = x0 * y1 - x1 * y0;
+            area += cross;
+            x += (x0 + x1) * cross;
+            y += (y0 + y1) * cross;
+        }
+
+        if area.abs() < f64::EPSILON {
+            let count = ring.len() as f64;
+            return Some(LatLon::new(
+                ring.iter().map(|point| point.lat).sum::<f64>() / count,
+                ring.iter().map(|point| point.lon).sum::<f64>() / count,
+            ));
+        }
+
+        Some(LatLon::new(origin.lat + y / (3.0 * area), origin.lon + x / (3.0 * area * scale)))
+    }
+
+    /// even-odd rule on raw lat/lon, fine for anything that does not cross the antimeridian
+    pub fn point_in_polygon(point: LatLon, ring: &[LatLon]) -> bool {
+        let mut inside = false;
+
+        for i in 0..ring.len() {
+            let a = ring[i];
+            let b = ring[(i + ring.len() - 1) % ring.len()];
+            if (a.lat > point.lat) != (b.lat > point.lat) && point.lon < (b.lon - a.lon) * (point.lat - a.lat) / (b.lat - a.lat) + a.lon {
+                inside = !inside;
+            }
+        }
+
+        inside
+    }
+
+    /// where segment `a`-`b` crosses segment `c`-`d`, as the fraction of the way from `a` to `b`
+    ///
+    /// both segments include their start but not their end, so a crossing exactly at a vertex shared by
+    /// consecutive segments counts once. planar in lat/lon, which is fine for the short edges of osm geometry
+    pub fn segment_intersection(a: LatLon, b: LatLon, c: LatLon, d: LatLon) -> Option<f64> {
+        let (rx, ry) = (b.lon - a.lon, b.lat - a.lat);
+        let (sx, sy) = (d.lon - c.lon, d.lat - c.lat);
+        let denominator = rx * sy - ry * sx;
+        if denominator == 0.0 { return None; }
+
+        let (qx, qy) = (c.lon - a.lon, c.lat - a.lat);
+        let t = (qx * sy - qy * sx) / denominator;
+        let u = (qx * ry - qy * rx) / denominator;
+        if (0.0..1.0).contains(&t) && (0.0..1.0).contains(&u) { Some(t) } else { None }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn crossings(a: LatLon, b: LatLon, ring: &[LatLon]) -> Vec<f64> {
+            ring.windows(2).filter_map(|edge| segment_intersection(a, b, edge[0], edge[1])).collect()
+        }
+
+        #[test]
+        fn crosses_through_a_shared_vertex_once() {
+            let square = [LatLon::new(0.0, 0.0), LatLon::new(0.0, 10.0), LatLon::new(10.0, 10.0), LatLon::new(10.0, 0.0), LatLon::new(0.0, 0.0)];
+            assert_eq!(crossings(LatLon::new(-5.0, -5.0), LatLon::new(5.0, 5.0), &square), vec![0.5]);
+            assert_eq!(crossings(LatLon::new(5.0, -5.0), LatLon::new(5.0, 5.0), &square), vec![0.5]);
+        }
+
+        #[test]
+        fn counts_a_line_vertex_on_an_edge_once() {
+            let edge = [LatLon::new(0.0, 0.0), LatLon::new(10.0, 0.0)];
+            let line = [LatLon::new(5.0, -5.0), LatLon::new(5.0, 0.0), LatLon::new(5.0, 5.0)];
+            let count: usize = line.windows(2).map(|segment| crossings(segment[0], segment[1], &edge).len()).sum();
+            assert_eq!(count, 1);
+        }
+
+        #[test]
+        fn ignores_parallel_segments() {
+            assert_eq!(segment_intersection(LatLon::new(0.0, 0.0), LatLon::new(0.0, 10.0), LatLon::new(0.0, 5.0), LatLon::new(0.0, 15.0)), None);
+        }
+    }
+}
+
+pub mod osm_reader {
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::fs::File;
+    use std::io::{self, BufReader, Read};
+    use std::rc::Rc;
+
+    use xml::attribute::OwnedAttribute;
+    use xml::reader::{EventReader, XmlEvent};
+
+    use crate::api::{post_stream, OVERPASS_URL};
+    use crate::geo::{BBox, LatLon};
+    use crate::osm::{complete_geometry, Metadata, Node, OsmElement, Relation, RelationMember, Way};
+
+    pub enum Element {
+        Node(Node),
+        Way(Way),
+        Relation(Relation),
+    }
+
+    impl Element {
+        pub fn element_type(&self) -> &'static str {
+            match self {
+                Element::Node(_) => Node::KIND,
+                Element::Way(_) => Way::KIND,
+                Element::Relation(_) => Relation::KIND,
+            }
+        }
+
+        pub fn id(&self) -> i64 {
+            match self {
+                Element::Node(node) => node.id,
+                Element::Way(way) => way.id,
+                Element::Relation(relation) => relation.id,
+            }
+        }
+
+        pub fn meta(&self) -> &Metadata {
+            match self {
+                Element::Node(node) => &node.meta,
+                Element::Way(way) => &way.meta,
+                Element::Relation(relation) => &relation.meta,
+            }
+        }
+    }
+
+    /// what `OsmReader` hands out: the osmChange action, if any, and the element itself
+    pub type ReadItem = (Option<ChangeAction>, Element);
+
+    pub type ElementMaps = (HashMap<i64, Node>, HashMap<i64, Way>, HashMap<i64, Relation>);
+
+    /// the osmChange block an element was found in
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum ChangeAction {
+        Create,
+        Modify,
+        Delete,
+    }
+
+    /// pull parser over `<osm>` and `<osmChange>` documents, handing out one element at a time
+    ///
+    /// understands the inline `out geom` / `out center` geometry the overpass parsers accept
+    pub struct OsmReader<R: Read> {
+        events: EventReader<R>,
+        action: Option<ChangeAction>,
+        current: Option<Element>,
+        /// `<nd>` locations of the way or member being read, kept only when none is missing, like the json parser does
+        geometry: Vec<Option<LatLon>>,
+        member: Option<RelationMember>,
+    }
+
+    impl<R: Read> OsmReader<R> {
+        pub fn new(source: R) -> OsmReader<R> {
+            OsmReader {
+                events: EventReader::new(source),
+                action: None,
+                current: None,
+                geometry: Vec::new(),
+                member: None,
+            }
+        }
+
+        /// `None` once the document is done
+        pub fn next_element(&mut self) -> Result<Option<ReadItem>, Box<dyn Error>> {
+            loop {
+                match self.events.next()? {
+                    XmlEvent::StartElement { name, attributes, .. } => self.start(&name.local_name, &attributes)?,
+                    XmlEvent::EndElement { name } => {
+                        if let Some(element) = self.end(&name.local_name) {
+                            return Ok(Some((self.action, element)));
+                        }
+                    }
+                    XmlEvent::EndDocument => return Ok(None),
+                    _ => {}
+                }
+            }
+        }
+
+        fn start(&mut self, name: &str, attributes: &[OwnedAttribute]) -> Result<(), Box<dyn Error>> {
+            let attribute = |key: &str| attributes.iter()
+                .find(|attribute| attribute.name.local_name == key)
+                .map(|attribute| attribute.value.as_str());
+            let number = |key: &str| attribute(key).and_then(|value| value.parse::<f64>().ok());
+            let location = || number("lat").zip(number("lon")).map(|(lat, lon)| LatLon::new(lat, lon));
+            let id = || -> Result<i64, Box<dyn Error>> {
+                Ok(attribute("id").or(attribute("ref")).ok_or(format!("<{}> without id", name))?.parse()?)
+            };
+            let meta = || Metadata {
+                version: attribute("version").and_then(|value| value.parse().ok()),
+                timestamp: attribute("timestamp").map(|value| value.to_string()),
+                uid: attribute("uid").and_then(|value| value.parse().ok()),
+                user: attribute("user").map(|value| value.to_string()),
+                changeset: attribute("changeset").and_then(|value| value.parse().ok()),
+            };
+
+            match name {
+                "create" => self.action = Some(ChangeAction::Create),
+                "modify" => self.action = Some(ChangeAction::Modify),
+                "delete" => self.action = Some(ChangeAction::Delete),
+                "node" => {
+                    // like the json parser a node needs its location, except in an osmChange delete where it plays no part
+                    let location = match (location(), self.action) {
+                        (Some(location), _) => location,
+                        (None, Some(ChangeAction::Delete)) => LatLon::new(0.0, 0.0),
+                        (None, _) => return Err(format!("<node> {} without lat/lon", id()?).into()),
+                    };
+                    self.current = Some(Element::Node(Node { id: id()?, meta: meta(), lat: location.lat, lon: location.lon, tags: HashMap::new() }));
+                }
+                "way" => {
+                    self.geometry.clear();
+                    self.current = Some(Element::Way(Way { id: id()?, meta: meta(), nodes: Vec::new(), tags: HashMap::new(), geometry: None, bounds: None, center: None }));
+                }
+                "relation" => {
+                    self.current = Some(Element::Relation(Relation { id: id()?, meta: meta(), members: Vec::new(), tags: HashMap::new(), bounds: None, center: None }));
+                }
+                "tag" => {
+                    let (key, value) = (attribute("k").unwrap_or(""), attribute("v").unwrap_or(""));
+                    let tags = match &mut self.current {
+                        Some(Element::Node(node)) => &mut node.tags,
+                        Some(Element::Way(way)) => &mut way.tags,
+                        Some(Element::Relation(relation)) => &mut relation.tags,
+                        None => return Ok(()),
+                    };
+                    tags.insert(key.to_string(), value.to_string());
+                }
+                "nd" => match (&mut self.member, &mut self.current) {
+                    (Some(_), _) => self.geometry.push(location()),
+                    (None, Some(Element::Way(way))) => {
+                        way.nodes.push(id()?);
+                        self.geometry.push(location());
+                    }
+                    _ => {}
+                },
+                "member" => {
+                    self.geometry.clear();
+                    self.member = Some(RelationMember {
+                        id: id()?,
+                        role: attribute("role").unwrap_or("").to_string(),
+                        element_type: attribute("type").unwrap_or("").to_string(),
+                        location: location(),
+                        geometry: None,
+                    });
+                }
+                "bounds" => {
+                    let bounds = match (number("minlat"), number("minlon"), number("maxlat"), number("maxlon")) {
+                        (Some(min_lat), Some(min_lon), Some(max_lat), Some(max_lon)) => BBox::new(min_lat, min_lon, max_lat, max_lon),
+                        _ => return Ok(()),
+                    };
+                    match &mut self.current {
+                        Some(Element::Way(way)) => way.bounds = Some(bounds),
+                        Some(Element::Relation(relation)) => relation.bounds = Some(bounds),
+                        _ => {}
+                    }
+                }
+                "center" => match &mut self.current {
+                    Some(Element::Way(way)) => way.center = location(),
+                    Some(Element::Relation(relation)) => relation.center = location(),
+                    _ => {}
+                },
+                _ => {}
+            }
+
+            Ok(())
+        }
+
+        fn end(&mut self, name: &str) -> Option<Element> {
+            match name {
+                "create" | "modify" | "delete" => {
+                    self.action = None;
+                    None
+                }
+                "member" => {
+                    if let (Some(mut member), Some(Element::Relation(relation))) = (self.member.take(), &mut self.current) {
+                        member.geometry = complete_geometry(Some(self.geometry.drain(..).collect()));
+                        relation.members.push(member);
+                    }
+                    None
+                }
+                "way" => {
+                    let mut element = self.current.take();
+                    if let Some(Element::Way(way)) = &mut element {
+                        way.geometry = complete_geometry(Some(self.geometry.drain(..).collect()));
+                    }
+                    element
+                }
+                "node" | "relation" => self.current.take(),
+                _ => None,
+            }
+        }
+    }
+
+    /// how far `read_all` got
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct Progress {
+        pub bytes: u64,
+        pub elements: usize,
+        pub done: bool,
+    }
+
+    /// elements between two progress reports
+    const PROGRESS_INTERVAL: usize = 10_000;
+
+    /// counts the bytes the xml parser pulls through
+    struct CountingReader<R: Read> {
+        inner: R,
+        bytes: Rc<Cell<u64>>,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let count = self.inner.read(buf)?;
+            self.bytes.set(self.bytes.get() + count as u64);
+            Ok(count)
+        }
+    }
+
+    /// hands every element of a whole document to `visit`, calling `progress` every few thousand elements and once at the end
+    fn for_each_element<R: Read>(source: R, mut progress: impl FnMut(Progress), mut visit: impl FnMut(Element)) -> Result<(), Box<dyn Error>> {
+        let bytes = Rc::new(Cell::new(0));
+        let mut reader = OsmReader::new(BufReader::new(CountingReader { inner: source, bytes: bytes.clone() }));
+        let mut elements = 0;
+
+        while let Some((_, element)) = reader.next_element()? {
+            visit(element);
+
+            elements += 1;
+            if elements % PROGRESS_INTERVAL == 0 {
+                progress(Progress { bytes: bytes.get(), elements, done: false });
+            }
+        }
+        progress(Progress { bytes: bytes.get(), elements, done: true });
+
+        Ok(())
+    }
+
+    /// loads a whole `.osm` file into the usual maps
+    pub fn read_all<R: Read>(source: R, progress: impl FnMut(Progress)) -> Result<ElementMaps, Box<dyn Error>> {
+        let mut nodes = HashMap::new();
+        let mut ways = HashMap::new();
+        let mut relations = HashMap::new();
+
+        for_each_element(source, progress, |element| match element {
+            Element::Node(node) => { nodes.insert(node.id, node); }
+            Element::Way(way) => { ways.insert(way.id, way); }
+            Element::Relation(relation) => { relations.insert(relation.id, relation); }
+        })?;
+
+        Ok((nodes, ways, relations))
+    }
+
+    /// like `read_all`, keeping only elements of kind `T`
+    pub fn read_elements<R: Read, T: OsmElement>(source: R, progress: impl FnMut(Progress)) -> Result<HashMap<i64, T>, Box<dyn Error>> {
+        let mut elements = HashMap::new();
+        for_each_element(source, progress, |element| {
+            if let Some(element) = T::from_element(element) {
+                elements.insert(element.id(), element);
+            }
+        })?;
+
+        Ok(elements)
+    }
+
+    pub fn read_file(path: &str) -> Result<ElementMaps, Box<dyn Error>> {
+        read_all(File::open(path)?, |_| {})
+    }
+
+    /// runs an `[out:xml]` overpass query, parsing the response while it downloads
+    pub async fn stream_overpass<T: OsmElement>(query: String) -> Result<HashMap<i64, T>, Box<dyn Error>> {
+        let response = post_stream(OVERPASS_URL, query).await?;
+        let result = tokio::task::spawn_blocking(move || {
+            read_elements(response, report_progress).map_err(|err| err.to_string())
+        }).await?;
+
+        Ok(result?)
+    }
+
+    fn report_progress(progress: Progress) {
+        eprint!("\rreceived {:.1} MB, {} elements", progress.bytes as f64 / 1_000_000.0, progress.elements);
+        if progress.done {
+            eprintln!();
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn drops_geometry_with_missing_points() {
+            let document = r#"<osm>
+                <way id="1"><nd ref="1" lat="1" lon="1"/><nd ref="2"/><nd ref="3" lat="3" lon="3"/></way>
+                <way id="2"><nd ref="1" lat="1" lon="1"/><nd ref="3" lat="3" lon="3"/></way>
+                <relation id="3">
+                    <member type="way" ref="1" role="outer"><nd lat="1" lon="1"/><nd/><nd lat="3" lon="3"/></member>
+                    <member type="way" ref="2" role="inner"><nd lat="1" lon="1"/><nd lat="3" lon="3"/></member>
+                </relation>
+            </osm>"#;
+            let (_, ways, relations) = read_all(document.as_bytes(), |_| {}).unwrap();
+
+            assert!(ways[&1].geometry.is_none());
+            assert_eq!(ways[&1].nodes, vec![1, 2, 3]);
+            assert_eq!(ways[&2].geometry.as_ref().map(|points| points.len()), Some(2));
+            let members = &relations[&3].members;
+            assert!(members[0].geometry.is_none());
+            assert_eq!(members[1].geometry.as_ref().map(|points| points.len()), Some(2));
+        }
+
+        #[test]
+        fn nodes_need_a_location() {
+            let error = read_all(r#"<osm><node id="1" lat="1"/></osm>"#.as_bytes(), |_| {}).err().unwrap();
+            assert_eq!(error.to_string(), "<node> 1 without lat/lon");
+
+            let mut reader = OsmReader::new(r#"<osmChange><delete><node id="1" version="2"/></delete></osmChange>"#.as_bytes());
+            let (action, element) = reader.next_element().unwrap().unwrap();
+            assert_eq!((action, element.id()), (Some(ChangeAction::Delete), 1));
+        }
+    }
+}
+
+pub mod osm_change {
+    use std::error::Error;
+    use std::fs::File;
+    use std::io::{BufReader, BufWriter, Read};
+
+    use crate::cli::Options;
+    use crate::dataset::OsmDataset;
+    use crate::osm_reader::{read_file, ChangeAction, OsmReader};
+    use crate::osm_xml::write_osm;
+
+    #[derive(Debug)]
+    pub enum ConflictKind {
+        /// `<create>` for an id we already have
+        AlreadyExists,
+        /// the change is not newer than what we have, so it was applied already or is based on stale data
+        StaleVersion { existing: u32, incoming: u32 },
+        /// `<delete>` for an element a loaded way or relation still uses, the api refuses those as well
+        StillReferenced,
+    }
+
+    #[derive(Debug)]
+    pub struct Conflict {
+        pub action: ChangeAction,
+        pub element_type: &'static str,
+        pub id: i64,
+        pub kind: ConflictKind,
+    }
+
+    #[derive(Debug, Default)]
+    pub struct ApplyReport {
+        pub created: usize,
+        pub modified: usize,
+        pub deleted: usize,
+        /// deletes for elements that were never loaded, normal when the dataset is an extract
+        pub skipped: usize,
+        pub conflicts: Vec<Conflict>,
+    }
+
+    /// applies an osmChange document in document order, conflicting changes are left out and reported
+    pub fn apply<R: Read>(source: R, dataset: &mut OsmDataset) -> Result<ApplyReport, Box<dyn Error>> {
+        let mut reader = OsmReader::new(source);
+        let mut report = ApplyReport::default();
+
+        while let Some((action, element)) = reader.next_element()? {
+            let action = match action {
+                Some(action) => action,
+                None => continue,
+            };
+
+            let existing_version = dataset.meta(element.element_type(), element.id()).map(|meta| meta.version);
+
+            let conflict = match (action, existing_version) {
+                (ChangeAction::Create, Some(_)) => Some(ConflictKind::AlreadyExists),
+                (ChangeAction::Modify, Some(Some(existing))) | (ChangeAction::Delete, Some(Some(existing))) => match element.meta().version {
+                    Some(incoming) if incoming <= existing => Some(ConflictKind::StaleVersion { existing, incoming }),
+                    _ => None,
+                },
+                _ => None,
+            }.or_else(|| {
+                let referenced = action == ChangeAction::Delete && dataset.is_referenced(element.element_type(), element.id());
+                referenced.then_some(ConflictKind::StillReferenced)
+            });
+
+            if let Some(kind) = conflict {
+                report.conflicts.push(Conflict { action, element_type: element.element_type(), id: element.id(), kind });
+                continue;
+            }
+
+            match action {
+                ChangeAction::Create | ChangeAction::Modify => {
+                    if action == ChangeAction::Create { report.created += 1 } else { report.modified += 1 }
+                    dataset.insert(element);
+                }
+                ChangeAction::Delete => {
+                    let removed = dataset.remove(element.element_type(), element.id());
+                    if removed { report.deleted += 1 } else { report.skipped += 1 }
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// `apply-osc --file data.osm --osc changes.osc [--out data.osm]`
+    pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+        let options = Options::parse(args)?;
+        let path = options.require("file")?;
+        let out = options.get("out").unwrap_or(path);
+
+        let mut dataset = OsmDataset::new(read_file(path)?);
+        let report = apply(BufReader::new(File::open(options.require("osc")?)?), &mut dataset)?;
+
+        println!("created {}, modified {}, deleted {}, skipped {} missing", report.created, report.modified, report.deleted, report.skipped);
+        for conflict in &report.conflicts {
+            println!("conflict: {:?} {}/{}: {:?}", conflict.action, conflict.element_type, conflict.id, conflict.kind);
+        }
+
+        write_osm(&mut BufWriter::new(File::create(out)?), &dataset)?;
+        println!("saved {}", out);
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::osm_reader::read_all;
+
+        const BASE: &str = r#"<osm version="0.6">
+            <node id="1" version="2" lat="43.73" lon="7.42"><tag k="amenity" v="cafe"/></node>
+            <node id="2" version="1" lat="43.74" lon="7.43"/>
+            <node id="3" version="1" lat="43.75" lon="7.44"/>
+            <way id="10" version="1"><nd ref="2"/><nd ref="3"/><tag k="highway" v="primary"/></way>
+            <relation id="20" version="1"><member type="way" ref="10" role=""/><tag k="type" v="route"/></relation>
+        </osm>"#;
+
+        fn apply_change(change: &str) -> (OsmDataset, ApplyReport) {
+            let mut dataset = OsmDataset::new(read_all(BASE.as_bytes(), |_| {}).unwrap());
+            let report = apply(change.as_bytes(), &mut dataset).unwrap();
+            (dataset, report)
+        }
+
+        fn amenity(dataset: &OsmDataset, id: i64) -> Option<&str> {
+            dataset.nodes().get(&id)?.tags.get("amenity").map(|value| value.as_str())
+        }
+
+        #[test]
+        fn creates_new_elements() {
+            let (dataset, report) = apply_change(r#"<osmChange><create>
+                <node id="4" version="1" lat="43.76" lon="7.45"><tag k="amenity" v="bench"/></node>
+            </create></osmChange>"#);
+            assert_eq!(report.created, 1);
+            assert!(report.conflicts.is_empty());
+            assert_eq!(amenity(&dataset, 4), Some("bench"));
+        }
+
+        #[test]
+        fn create_over_existing_is_a_conflict() {
+            let (dataset, report) = apply_change(r#"<osmChange><create>
+                <node id="1" version="1" lat="0" lon="0"><tag k="amenity" v="bench"/></node>
+            </create></osmChange>"#);
+            assert_eq!(report.created, 0);
+            assert!(matches!(report.conflicts[..], [Conflict { kind: ConflictKind::AlreadyExists, id: 1, .. }]));
+            assert_eq!(amenity(&dataset, 1), Some("cafe"));
+        }
+
+        #[test]
+        fn modifies_with_a_newer_version() {
+            let (dataset, report) = apply_change(r#"<osmChange><modify>
+                <node id="1" version="3" lat="43.73" lon="7.42"><tag k="amenity" v="bar"/></node>
+            </modify></osmChange>"#);
+            assert_eq!(report.modified, 1);
+            assert!(report.conflicts.is_empty());
+            assert_eq!(amenity(&dataset, 1), Some("bar"));
+        }
+
+        #[test]
+        fn modify_with_a_stale_version_is_a_conflict() {
+            let (dataset, report) = apply_change(r#"<osmChange><modify>
+                <node id="1" version="2" lat="43.73" lon="7.42"><tag k="amenity" v="bar"/></node>
+            </modify></osmChange>"#);
+            assert_eq!(report.modified, 0);
+            assert!(matches!(report.conflicts[..], [Conflict { kind: ConflictKind::StaleVersion { existing: 2, incoming: 2 }, .. }]));
+            assert_eq!(amenity(&dataset, 1), Some("cafe"));
+        }
+
+        #[test]
+        fn modify_without_a_version_is_applied() {
+            let (dataset, report) = apply_change(r#"<osmChange><modify>
+                <node id="1" lat="43.73" lon="7.42"><tag k="amenity" v="bar"/></node>
+            </modify></osmChange>"#);
+            assert_eq!(report.modified, 1);
+            assert_eq!(amenity(&dataset, 1), Some("bar"));
+        }
+
+        #[test]
+        fn deletes_with_a_newer_version() {
+            let (dataset, report) = apply_change(r#"<osmChange><delete>
+                <node id="1" version="3" lat="43.73" lon="7.42"/>
+            </delete></osmChange>"#);
+            assert_eq!(report.deleted, 1);
+            assert!(!dataset.contains("node", 1));
+        }
+
+        #[test]
+        fn delete_with_a_stale_version_is_a_conflict() {
+            let (dataset, report) = apply_change(r#"<osmChange><delete>
+                <node id="1" version="1" lat="43.73" lon="7.42"/>
+            </delete></osmChange>"#);
+            assert_eq!(report.deleted, 0);
+            assert!(matches!(report.conflicts[..], [Conflict { kind: ConflictKind::StaleVersion { existing: 2, incoming: 1 }, .. }]));
+            assert!(dataset.contains("node", 1));
+        }
+
+        #[test]
+        fn delete_of_a_missing_element_is_skipped() {
+            let (_, report) = apply_change(r#"<osmChange><delete>
+                <node id="99" version="1" lat="0" lon="0"/>
+            </delete></osmChange>"#);
+            assert_eq!(report.skipped, 1);
+            assert!(report.conflicts.is_empty());
+        }
+
+        #[test]
+        fn delete_of_a_referenced_element_is_a_conflict() {
+            let (dataset, report) = apply_change(r#"<osmChange><delete>
+                <node id="2" version="2" lat="43.74" lon="7.43"/>
+                <way id="10" version="2"/>
+            </delete></osmChange>"#);
+            assert_eq!(report.deleted, 0);
+            assert!(matches!(report.conflicts[..], [
+                Conflict { kind: ConflictKind::StillReferenced, id: 2, .. },
+                Conflict { kind: ConflictKind::StillReferenced, id: 10, .. },
+            ]));
+            assert!(dataset.contains("node", 2) && dataset.contains("way", 10));
+        }
+
+        #[test]
+        fn deletes_in_document_order_free_their_members() {
+            let (dataset, report) = apply_change(r#"<osmChange><delete>
+                <relation id="20" version="2"/>
+                <way id="10" version="2"/>
+                <node id="2" version="2" lat="43.74" lon="7.43"/>
+            </delete></osmChange>"#);
+            assert_eq!(report.deleted, 3);
+            assert!(report.conflicts.is_empty());
+            assert!(!dataset.contains("node", 2));
+        }
+    }
+}
+
+pub mod source {
+    use std::error::Error;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use crate::area::{fetch_boundary, AreaSelector, Boundary};
+    use crate::cli::Options;
+    use crate::dataset::OsmDataset;
+    use crate::osm::{fetch, GeometryMode, Node, OutputFormat, Relation, Scope, Way};
+    use crate::mvt;
+    use crate::osm_change;
+    use crate::osm_reader::read_file;
+    use crate::tiles::TileSource;
+
+    /// loads elements from whichever source the command line names:
+    /// `--file data.osm [--osc changes.osc]`, vector tiles with `--mvt tiles/|tiles.mbtiles [--zoom 14]`, or from overpass by `--bbox min_lat,min_lon,max_lat,max_lon`
+    /// or `--area Monaco [--admin-level 2]` / `--area relation/1124039`, with `[--format json|xml]`
+    ///
+    /// with `--area` the data is clipped to the boundary, which is handed back for drawing
+    pub async fn load(options: &Options, geometry_mode: GeometryMode) -> Result<(OsmDataset, Option<Boundary>), Box<dyn Error>> {
+        let boundary = match options.get("area") {
+            Some(area) => {
+                let admin_level = options.get("admin-level").map(|level| level.parse()).transpose()?;
+                Some(fetch_boundary(&AreaSelector::parse(area, admin_level)).await?)
+            }
+            None => None,
+        };
+
+        let dataset = if let Some(path) = options.get("mvt") {
+            let bbox = match &boundary {
+                Some(boundary) => boundary.bbox,
+                None => options.bbox("bbox")?,
+            };
+            let zoom = options.get("zoom").map(|zoom| zoom.parse()).transpose()?.unwrap_or(14);
+            mvt::load(&TileSource::open(path, mvt::EXTENSIONS)?, &bbox, zoom)?
+        } else {
+            match options.get("file") {
+                Some(path) => {
+                    let mut dataset = OsmDataset::new(read_file(path)?);
+                    if let Some(osc) = options.get("osc") {
+                        let report = osm_change::apply(BufReader::new(File::open(osc)?), &mut dataset)?;
+                        if !report.conflicts.is_empty() {
+                            eprintln!("{} conflicting changes in {} were skipped", report.conflicts.len(), osc);
+                        }
+                    }
+                    dataset
+                }
+                None => {
+                    let scope = match &boundary {
+                        Some(boundary) => Scope::Area(boundary.relation.id),
+                        None => Scope::BBox(options.bbox("bbox")?),
+                    };
+                    let output_format = match options.get("format") {
+                        Some("json") => OutputFormat::JSON,
+                        Some("xml") | None => OutputFormat::XML,
+                        Some(format) => return Err(format!("unknown format: {}", format).into()),
+                    };
+
+                    let nodes = fetch::<Node>(output_format, GeometryMode::None, &scope).await?;
+                    let ways = fetch::<Way>(output_format, geometry_mode, &scope).await?;
+                    let relations = fetch::<Relation>(output_format, geometry_mode, &scope).await?;
+                    OsmDataset::new((nodes, ways, relations))
+                }
+            }
+        };
+
+        match boundary {
+            Some(boundary) => Ok((boundary.clip(&dataset), Some(boundary))),
+            None => Ok((dataset, None)),
+        }
+    }
+}
+
+pub mod stats {
+    use std::collections::HashMap;
+    use std::error::Error;
+
+    use serde::Serialize;
+
+    use crate::cli::Options;
+    use crate::dataset::{IssueKind, OsmDataset};
+    use crate::geo::polyline_length;
+    use crate::osm::GeometryMode;
+    use crate::source;
+
+    #[derive(Serialize)]
+    pub struct TagCount {
+        pub tag: String,
+        pub count: usize,
+    }
+
+    #[derive(Serialize)]
+    pub struct DanglingReference {
+        pub from: String,
+        pub to: String,
+    }
+
+    #[derive(Serialize)]
+    pub struct Stats {
+        pub nodes: usize,
+        pub ways: usize,
+        pub relations: usize,
+        pub top_keys: Vec<TagCount>,
+        pub top_tags: Vec<TagCount>,
+        /// kilometers per `highway` value
+        pub highway_km: Vec<(String, f64)>,
+        pub buildings: usize,
+        pub dangling_references: Vec<DanglingReference>,
+        pub degenerate_ways: usize,
+    }
+
+    pub fn collect(dataset: &OsmDataset, top: usize) -> Stats {
+        let (nodes, ways, relations) = (dataset.nodes(), dataset.ways(), dataset.relations());
+        let mut keys: HashMap<&str, usize> = HashMap::new();
+        let mut tags: HashMap<String, usize> = HashMap::new();
+        let all_tags = nodes.values().map(|node| &node.tags)
+            .chain(ways.values().map(|way| &way.tags))
+            .chain(relations.values().map(|relation| &relation.tags));
+        for element_tags in all_tags {
+            for (key, value) in element_tags {
+                *keys.entry(key).or_default() += 1;
+                *tags.entry(format!("{}={}", key, value)).or_default() += 1;
+            }
+        }
+
+        let mut highway_km: HashMap<String, f64> = HashMap::new();
+        for way in ways.values() {
+            if let Some(highway) = way.tags.get("highway") {
+                *highway_km.entry(highway.clone()).or_default() += polyline_length(&dataset.way_coordinates(way)) / 1000.0;
+            }
+        }
+        let mut highway_km: Vec<(String, f64)> = highway_km.into_iter().collect();
+        highway_km.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let buildings = nodes.values().filter(|node| node.tags.contains_key("building")).count()
+            + ways.values().filter(|way| way.tags.contains_key("building")).count()
+            + relations.values().filter(|relation| relation.tags.contains_key("building")).count();
+
+        let mut dangling_references = Vec::new();
+        let mut degenerate_ways = 0;
+        for issue in dataset.validate() {
+            let to = match issue.kind {
+                IssueKind::MissingNode(id) => format!("node/{}", id),
+                IssueKind::MissingMember { element_type, id } => format!("{}/{}", element_type, id),
+                IssueKind::DegenerateWay => {
+                    degenerate_ways += 1;
+                    continue;
+                }
+            };
+            dangling_references.push(DanglingReference { from: format!("{}/{}", issue.element_type, issue.id), to });
+        }
+
+        Stats {
+            nodes: nodes.len(),
+            ways: ways.len(),
+            relations: relations.len(),
+            top_keys: top_counts(keys.into_iter().map(|(key, count)| (key.to_string(), count)), top),
+            top_tags: top_counts(tags.into_iter(), top),
+            highway_km,
+            buildings,
+            dangling_references,
+            degenerate_ways,
+        }
+    }
+
+    fn top_counts<I: Iterator<Item = (String, usize)>>(counts: I, top: usize) -> Vec<TagCount> {
+        let mut counts: Vec<TagCount> = counts.map(|(tag, count)| TagCount { tag, count }).collect();
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tag.cmp(&b.tag)));
+        counts.truncate(top);
+        counts
+    }
+
+    pub fn print_table(stats: &Stats) {
+        println!("{:<24}{:>12}", "nodes", stats.nodes);
+        println!("{:<24}{:>12}", "ways", stats.ways);
+        println!("{:<24}{:>12}", "relations", stats.relations);
+        println!("{:<24}{:>12}", "buildings", stats.buildings);
+        println!("{:<24}{:>12}", "dangling references", stats.dangling_references.len());
+        println!("{:<24}{:>12}", "degenerate ways", stats.degenerate_ways);
+
+        println!();
+        println!("{:<24}{:>12}", "top keys", "count");
+        for key in &stats.top_keys {
+            println!("{:<24}{:>12}", key.tag, key.count);
+        }
+
+        println!();
+        println!("{:<36}{:>12}", "top tags", "count");
+        for tag in &stats.top_tags {
+            println!("{:<36}{:>12}", tag.tag, tag.count);
+        }
+
+        println!();
+        println!("{:<24}{:>12}", "highway", "km");
+        for (highway, km) in &stats.highway_km {
+            println!("{:<24}{:>12.3}", highway, km);
+        }
+
+        if !stats.dangling_references.is_empty() {
+            println!();
+            println!("dangling references");
+            for reference in &stats.dangling_references {
+                println!("  {} -> {}", reference.from, reference.to);
+            }
+        }
+    }
+
+    /// `stats (--bbox min_lat,min_lon,max_lat,max_lon | --file data.osm [--osc changes.osc]) [--top 10] [--json]`
+    #[tokio::main]
+    pub async fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+        let options = Options::parse(args)?;
+        let top = options.get("top").map(|top| top.parse()).transpose()?.unwrap_or(10);
+
+        let (dataset, _) = source::load(&options, GeometryMode::None).await?;
+        let stats = collect(&dataset, top);
+
+        if options.flag("json") {
+            println!("{}", serde_json::to_string_pretty(&stats)?);
+        } else {
+            print_table(&stats);
+        }
+
+        Ok(())
+    }
+}
+
+pub mod dataset {
+    use std::collections::HashMap;
+
+    use crate::geo::{BBox, LatLon};
+    use crate::osm::{Metadata, Node, OsmElement, Relation, Way};
+    use crate::osm_reader::{Element, ElementMaps};
+
+    #[derive(Debug)]
+    pub enum IssueKind {
+        /// a node reference of a way without inline geometry that is not loaded
+        MissingNode(i64),
+        /// a relation member that is not loaded, normal for relations reaching outside an extract
+        MissingMember { element_type: String, id: i64 },
+        /// fewer than two distinct nodes, or a closed way with fewer than four
+        DegenerateWay,
+    }
+
+    #[derive(Debug)]
+    pub struct Issue {
+        pub element_type: &'static str,
+        pub id: i64,
+        pub kind: IssueKind,
+    }
+
+    /// the nodes, ways and relations of one extract, with the reverse lookups between them
+    ///
+    /// changes go through `insert` and `remove` so the lookups stay in sync
+    #[derive(Default)]
+    pub struct OsmDataset {
+        nodes: HashMap<i64, Node>,
+        ways: HashMap<i64, Way>,
+        relations: HashMap<i64, Relation>,
+        /// node id -> ways referencing it
+        node_ways: HashMap<i64, Vec<i64>>,
+        /// (member type, member id) -> relations listing it
+        member_relations: HashMap<(String, i64), Vec<i64>>,
+    }
+
+    impl OsmDataset {
+        pub fn new((nodes, ways, relations): ElementMaps) -> OsmDataset {
+            let mut dataset = OsmDataset { nodes, ..Default::default() };
+            for (_, way) in ways {
+                dataset.insert(Element::Way(way));
+            }
+            for (_, relation) in relations {
+                dataset.insert(Element::Relation(relation));
+            }
+
+            dataset
+        }
+
+        pub fn nodes(&self) -> &HashMap<i64, Node> {
+            &self.nodes
+        }
+
+        pub fn ways(&self) -> &HashMap<i64, Way> {
+            &self.ways
+        }
+
+        pub fn relations(&self) -> &HashMap<i64, Relation> {
+            &self.relations
+        }
+
+        pub fn node(&self, id: i64) -> Option<&Node> {
+            self.nodes.get(&id)
+        }
+
+        pub fn way(&self, id: i64) -> Option<&Way> {
+            self.ways.get(&id)
+        }
+
+        pub fn relation(&self, id: i64) -> Option<&Relation> {
+            self.relations.get(&id)
+        }
+
+        /// metadata of any element by its `node`/`way`/`relation` type name, `None` when it is not loaded
+        pub fn meta(&self, element_type: &str, id: i64) -> Option<&Metadata> {
+            match element_type {
+                "node" => self.nodes.get(&id).map(|node| node.meta()),
+                "way" => self.ways.get(&id).map(|way| way.meta()),
+                "relation" => self.relations.get(&id).map(|relation| relation.meta()),
+                _ => None,
+            }
+        }
+
+        pub fn contains(&self, element_type: &str, id: i64) -> bool {
+            self.meta(element_type, id).is_some()
+        }
+
+        /// adds or replaces an element
+        pub fn insert(&mut self, element: Element) {
+            match element {
+                Element::Node(node) => {
+                    self.nodes.insert(node.id, node);
+                }
+                Element::Way(way) => {
+                    self.unindex_way(way.id);
+                    for node_id in &way.nodes {
+                        let parents = self.node_ways.entry(*node_id).or_default();
+                        if !parents.contains(&way.id) {
+                            parents.push(way.id);
+                        }
+                    }
+                    self.ways.insert(way.id, way);
+                }
+                Element::Relation(relation) => {
+                    self.unindex_relation(relation.id);
+                    for member in &relation.members {
+                        let parents = self.member_relations.entry((member.element_type.clone(), member.id)).or_default();
+                        if !parents.contains(&relation.id) {
+                            parents.push(relation.id);
+                        }
+                    }
+                    self.relations.insert(relation.id, relation);
+                }
+            }
+        }
+
+        /// `false` when there was nothing to remove
+        pub fn remove(&mut self, element_type: &str, id: i64) -> bool {
+            match element_type {
+                "node" => self.nodes.remove(&id).is_some(),
+                "way" => {
+                    self.unindex_way(id);
+                    self.ways.remove(&id).is_some()
+                }
+                "relation" => {
+                    self.unindex_relation(id);
+                    self.relations.remove(&id).is_some()
+                }
+                _ => false,
+            }
+        }
+
+        fn unindex_way(&mut self, id: i64) {
+            let Some(way) = self.ways.get(&id) else { return };
+            for node_id in &way.nodes {
+                if let Some(parents) = self.node_ways.get_mut(node_id) {
+                    parents.retain(|parent| *parent != id);
+                }
+            }
+        }
+
+        fn unindex_relation(&mut self, id: i64) {
+            let Some(relation) = self.relations.get(&id) else { return };
+            for member in &relation.members {
+                if let Some(parents) = self.member_relations.get_mut(&(member.element_type.clone(), member.id)) {
+                    parents.retain(|parent| *parent != id);
+                }
+            }
+        }
+
+        pub fn way_coordinates(&self, way: &Way) -> Vec<LatLon> {
+            way.coordinates(&self.nodes)
+        }
+
+        /// loaded ways that use the node
+        pub fn parent_ways(&self, node_id: i64) -> impl Iterator<Item = &Way> {
+            self.node_ways.get(&node_id).into_iter().flatten().filter_map(|id| self.ways.get(id))
+        }
+
+        /// loaded relations listing the element as a member
+        pub fn parent_relations(&self, element_type: &str, id: i64) -> impl Iterator<Item = &Relation> {
+            self.member_relations.get(&(element_type.to_string(), id)).into_iter().flatten().filter_map(|id| self.relations.get(id))
+        }
+
+        /// whether a loaded way or relation still uses the element
+        pub fn is_referenced(&self, element_type: &str, id: i64) -> bool {
+            (element_type == Node::KIND && self.parent_ways(id).next().is_some()) || self.parent_relations(element_type, id).next().is_some()
+        }
+
+        /// bounds of every node and inline way geometry, `None` for an empty dataset
+        pub fn bbox(&self) -> Option<BBox> {
+            let points: Vec<LatLon> = self.nodes.values().map(|node| node.location())
+                .chain(self.ways.values().filter_map(|way| way.geometry.as_ref()).flatten().copied())
+                .collect();
+            BBox::from_points(points.iter())
+        }
+
+        /// referential integrity problems, sorted by element
+        pub fn validate(&self) -> Vec<Issue> {
+            let mut issues = Vec::new();
+
+            for way in self.ways.values() {
+                if way.geometry.is_none() {
+                    for node_id in way.nodes.iter().filter(|node_id| !self.nodes.contains_key(node_id)) {
+                        issues.push(Issue { element_type: Way::KIND, id: way.id, kind: IssueKind::MissingNode(*node_id) });
+                    }
+                }
+
+                let degenerate = match (&way.geometry, way.nodes.is_empty()) {
+                    // vector tile ways and clipped pieces have no node references, only points
+                    (Some(geometry), true) => {
+                        let closed = geometry.len() > 1 && geometry.first() == geometry.last();
+                        !geometry.iter().any(|point| *point != geometry[0]) || (closed && geometry.len() < 4)
+                    }
+                    _ => {
+                        let mut distinct = way.nodes.clone();
+                        distinct.sort();
+                        distinct.dedup();
+                        let closed = way.nodes.len() > 1 && way.nodes.first() == way.nodes.last();
+                        distinct.len() < 2 || (closed && way.nodes.len() < 4)
+                    }
+                };
+                if degenerate {
+                    issues.push(Issue { element_type: Way::KIND, id: way.id, kind: IssueKind::DegenerateWay });
+                }
+            }
+
+            for relation in self.relations.values() {
+                for member in relation.members.iter().filter(|member| !self.contains(&member.element_type, member.id)) {
+                    issues.push(Issue {
+                        element_type: Relation::KIND,
+                        id: relation.id,
+                        kind: IssueKind::MissingMember { element_type: member.element_type.clone(), id: member.id },
+                    });
+                }
+            }
+
+            issues.sort_by_key(|issue| (issue.element_type, issue.id));
+            issues
+        }
+    }
+}
+
+pub mod area {
+    use std::collections::HashMap;
+    use std::error::Error;
+
+    use sdl2::gfx::primitives::DrawRenderer;
+    use sdl2::pixels::Color;
+    use sdl2::render::{Canvas, RenderTarget};
+
+    use crate::camera::Camera;
+    use crate::dataset::OsmDataset;
+    use crate::geo::{point_in_polygon, segment_intersection, BBox, LatLon};
+    use crate::geojson::multipolygon;
+    use crate::osm::{run_query, Metadata, OutputFormat, Relation, Way};
+    use crate::osm_reader::Element;
+
+    /// how a boundary relation is picked
+    pub enum AreaSelector {
+        Relation(i64),
+        /// an administrative boundary by its `name`, the lowest `admin_level` wins when several match
+        Name { name: String, admin_level: Option<u8> },
+    }
+
+    impl AreaSelector {
+        /// `relation/1124039` or a bare id select by id, anything else is a name
+        pub fn parse(value: &str, admin_level: Option<u8>) -> AreaSelector {
+            let id = value.strip_prefix("relation/").unwrap_or(value);
+            match id.parse() {
+                Ok(id) => AreaSelector::Relation(id),
+                Err(_) => AreaSelector::Name { name: value.to_string(), admin_level },
+            }
+        }
+
+        fn query(&self) -> String {
+            match self {
+                AreaSelector::Relation(id) => format!("[out:json]; rel({}); out geom;", id),
+                AreaSelector::Name { name, admin_level } => {
+                    let level = admin_level.map(|level| format!("[\"admin_level\"=\"{}\"]", level)).unwrap_or_default();
+                    format!("[out:json]; rel[\"boundary\"=\"administrative\"][\"name\"=\"{}\"]{}; out geom;", escape(name), level)
+                }
+            }
+        }
+    }
+
+    fn escape(value: &str) -> String {
+        value.replace('\\', "\\\\").replace('"', "\\\"")
+    }
+
+    /// a boundary relation with its rings assembled
+    pub struct Boundary {
+        pub relation: Relation,
+        /// outer ring first, then its holes
+        pub polygons: Vec<Vec<Vec<LatLon>>>,
+        pub bbox: BBox,
+    }
+
+    impl Boundary {
+        pub fn from_relation(relation: Relation) -> Result<Boundary, Box<dyn Error>> {
+            let polygons = multipolygon(&relation, &OsmDataset::default());
+            let bbox = BBox::from_points(polygons.iter().flatten().flatten())
+                .ok_or(format!("relation {} has no closed outer ring", relation.id))?;
+            Ok(Boundary { relation, polygons, bbox })
+        }
+
+        pub fn contains(&self, point: LatLon) -> bool {
+            self.bbox.contains(point) && self.polygons.iter().any(|polygon| {
+                point_in_polygon(point, &polygon[0]) && !polygon[1..].iter().any(|hole| point_in_polygon(point, hole))
+            })
+        }
+
+        fn edges(&self) -> impl Iterator<Item = (LatLon, LatLon)> + '_ {
+            self.polygons.iter().flatten().flat_map(|ring| ring.windows(2).map(|edge| (edge[0], edge[1])))
+        }
+
+        /// the parts of a polyline inside the boundary, with points added where it crosses an edge
+        ///
+        /// each part comes with the indices of the original points it kept
+        pub fn clip_polyline(&self, points: &[LatLon]) -> Vec<(Vec<LatLon>, Vec<usize>)> {
+            let mut parts = Vec::new();
+            let mut current: Option<(Vec<LatLon>, Vec<usize>)> = None;
+
+            for (index, point) in points.iter().enumerate() {
+                if index > 0 {
+                    let previous = points[index - 1];
+                    let mut crossings: Vec<f64> = self.edges()
+                        .filter_map(|(c, d)| segment_intersection(previous, *point, c, d))
+                        .collect();
+                    crossings.sort_by(|a, b| a.total_cmp(b));
+
+                    for t in crossings {
+                        let crossing = LatLon::new(previous.lat + (point.lat - previous.lat) * t, previous.lon + (point.lon - previous.lon) * t);
+                        match current.take() {
+                            Some((mut part, kept)) => {
+                                part.push(crossing);
+                                parts.push((part, kept));
+                            }
+                            None => current = Some((vec![crossing], Vec::new())),
+                        }
+                    }
+                } else if self.contains(*point) {
+                    current = Some((Vec::new(), Vec::new()));
+                }
+
+                if let Some((part, kept)) = current.as_mut() {
+                    part.push(*point);
+                    kept.push(index);
+                }
+            }
+            parts.extend(current);
+
+            parts.into_iter().filter(|(part, _)| part.len() >= 2).collect()
+        }
+
+        /// whether an area's ring reaches into the boundary: a vertex inside, an edge crossing it, or the whole boundary enclosed
+        fn overlaps_ring(&self, ring: &[LatLon]) -> bool {
+            ring.iter().any(|point| self.contains(*point))
+                || ring.windows(2).any(|edge| self.edges().any(|(c, d)| segment_intersection(edge[0], edge[1], c, d).is_some()))
+                || self.polygons.iter().any(|polygon| point_in_polygon(polygon[0][0], ring))
+        }
+
+        /// a copy of `dataset` limited to the boundary
+        ///
+        /// nodes outside are dropped and lines are cut at the boundary, pieces after the first get fresh negative ids
+        /// below any already in the dataset, the way new osm elements do; areas and relations touching the inside are kept whole and left to `draw_mask`
+        pub fn clip(&self, dataset: &OsmDataset) -> OsmDataset {
+            let mut nodes = HashMap::new();
+            let mut ways = HashMap::new();
+            let mut relations = HashMap::new();
+            let mut next_id = dataset.ways().keys().min().map_or(0, |id| (*id).min(0)) - 1;
+
+            for way in dataset.ways().values() {
+                let coordinates = dataset.way_coordinates(way);
+                if way.is_area() {
+                    if self.overlaps_ring(&coordinates) {
+                        ways.insert(way.id, way.clone());
+                    }
+                    continue;
+                }
+
+                for (piece, (part, kept)) in self.clip_polyline(&coordinates).into_iter().enumerate() {
+                    let id = match piece {
+                        0 => way.id,
+                        _ => {
+                            next_id -= 1;
+                            next_id + 1
+                        }
+                    };
+                    // a cut piece also holds the crossing points, which have no nodes, so it keeps only its geometry
+                    let cut = kept.len() != part.len();
+                    let node_ids = if !cut && way.nodes.len() == coordinates.len() { kept.iter().map(|index| way.nodes[*index]).collect() } else { Vec::new() };
+                    let meta = if id == way.id { way.meta.clone() } else { Metadata::default() };
+                    ways.insert(id, Way { id, meta, nodes: node_ids, geometry: Some(part), ..way.clone() });
+                }
+            }
+
+            for node in dataset.nodes().values() {
+                let used = dataset.parent_ways(node.id).any(|way| ways.get(&way.id).is_some_and(|kept| kept.nodes.contains(&node.id)));
+                if used || self.contains(node.location()) {
+                    nodes.insert(node.id, node.clone());
+                }
+            }
+
+            for relation in dataset.relations().values() {
+                let touches = relation.members.iter().any(|member| {
+                    let kept = match member.element_type.as_str() {
+                        "node" => nodes.contains_key(&member.id),
+                        "way" => ways.contains_key(&member.id),
+                        _ => false,
+                    };
+                    kept || member.location.is_some_and(|point| self.contains(point))
+                        || member.geometry.iter().flatten().any(|point| self.contains(*point))
+                });
+                if touches {
+                    relations.insert(relation.id, relation.clone());
+                }
+            }
+
+            let mut clipped = OsmDataset::new((nodes, HashMap::new(), relations));
+            for (_, way) in ways {
+                clipped.insert(Element::Way(way));
+            }
+            clipped
+        }
+
+        /// paints everything outside the boundary, so the map looks cut to its shape
+        pub fn draw_mask<T: RenderTarget>(&self, canvas: &mut Canvas<T>, camera: &Camera, color: Color) {
+            let (width, height) = (camera.viewport_width as i16, camera.viewport_height as i16);
+            let corners = [(-1, -1), (width + 1, -1), (width + 1, height + 1), (-1, height + 1), (-1, -1)];
+
+            // one even-odd polygon: the viewport plus every ring, each joined back to the first corner
+            let mut xs: Vec<i16> = corners.iter().map(|corner| corner.0).collect();
+            let mut ys: Vec<i16> = corners.iter().map(|corner| corner.1).collect();
+            for ring in self.polygons.iter().flatten() {
+                for point in ring {
+                    let (x, y) = camera.project(*point);
+                    xs.push(x.clamp(i16::MIN as i32, i16::MAX as i32) as i16);
+                    ys.push(y.clamp(i16::MIN as i32, i16::MAX as i32) as i16);
+                }
+                xs.push(-1);
+                ys.push(-1);
+            }
+
+            let _ = canvas.filled_polygon(&xs, &ys, color);
+        }
+    }
+
+    /// looks the boundary up on overpass
+    pub async fn fetch_boundary(selector: &AreaSelector) -> Result<Boundary, Box<dyn Error>> {
+        let relations = run_query::<Relation>(OutputFormat::JSON, selector.query()).await?;
+        let admin_level = |relation: &Relation| relation.tags.get("admin_level").and_then(|level| level.parse::<u8>().ok()).unwrap_or(u8::MAX);
+        let relation = relations.into_values()
+            .min_by_key(|relation| (admin_level(relation), relation.id))
+            .ok_or("no matching boundary relation")?;
+
+        Boundary::from_relation(relation)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::osm::RelationMember;
+        use crate::osm_reader::read_all;
+
+        /// a square boundary 0.01 degrees across with its corner at (0, 0)
+        fn boundary() -> Boundary {
+            let ring = [(0.0, 0.0), (0.0, 0.01), (0.01, 0.01), (0.01, 0.0), (0.0, 0.0)].map(|(lat, lon)| LatLon::new(lat, lon));
+            let outer = RelationMember { id: 1, role: "outer".to_string(), element_type: "way".to_string(), location: None, geometry: Some(ring.to_vec()) };
+            Boundary::from_relation(Relation { id: 1, meta: Metadata::default(), members: vec![outer], tags: HashMap::new(), bounds: None, center: None }).unwrap()
+        }
+
+        fn clip(data: &str) -> OsmDataset {
+            boundary().clip(&OsmDataset::new(read_all(data.as_bytes(), |_| {}).unwrap()))
+        }
+
+        #[test]
+        fn keeps_areas_enclosing_the_boundary() {
+            let clipped = clip(r#"<osm version="0.6">
+                <node id="1" lat="-1" lon="-1"/><node id="2" lat="-1" lon="1"/><node id="3" lat="1" lon="1"/><node id="4" lat="1" lon="-1"/>
+                <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/><nd ref="4"/><nd ref="1"/><tag k="landuse" v="forest"/></way>
+                <node id="5" lat="2" lon="2"/><node id="6" lat="2" lon="3"/><node id="7" lat="3" lon="3"/>
+                <way id="11"><nd ref="5"/><nd ref="6"/><nd ref="7"/><nd ref="5"/><tag k="landuse" v="meadow"/></way>
+            </osm>"#);
+            assert!(clipped.ways().contains_key(&10));
+            assert!(!clipped.ways().contains_key(&11));
+        }
+
+        #[test]
+        fn cut_lines_keep_only_their_geometry() {
+            let clipped = clip(r#"<osm version="0.6">
+                <node id="1" lat="0.005" lon="-0.005"/><node id="2" lat="0.005" lon="0.005"/><node id="3" lat="0.005" lon="0.008"/>
+                <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/><tag k="highway" v="primary"/></way>
+                <node id="4" lat="0.002" lon="0.002"/><node id="5" lat="0.003" lon="0.003"/>
+                <way id="11"><nd ref="4"/><nd ref="5"/><tag k="highway" v="footway"/></way>
+            </osm>"#);
+            let cut = &clipped.ways()[&10];
+            assert!(cut.nodes.is_empty());
+            assert_eq!(cut.geometry.as_ref().map(|geometry| geometry.len()), Some(3));
+            assert_eq!(clipped.ways()[&11].nodes, vec![4, 5]);
+        }
+
+        #[test]
+        fn new_pieces_do_not_reuse_negative_ids() {
+            let clipped = clip(r#"<osm version="0.6">
+                <node id="1" lat="0.005" lon="-0.005"/><node id="2" lat="0.005" lon="0.005"/><node id="3" lat="0.005" lon="0.015"/>
+                <node id="4" lat="0.006" lon="0.02"/><node id="5" lat="0.006" lon="0.008"/>
+                <way id="-1"><nd ref="1"/><nd ref="2"/><nd ref="3"/><nd ref="4"/><nd ref="5"/><tag k="highway" v="primary"/></way>
+                <node id="6" lat="0.002" lon="0.002"/><node id="7" lat="0.003" lon="0.003"/>
+                <way id="-2"><nd ref="6"/><nd ref="7"/><tag k="highway" v="footway"/></way>
+            </osm>"#);
+            assert_eq!(clipped.ways()[&-2].nodes, vec![6, 7]);
+            assert!(clipped.ways().contains_key(&-1));
+            assert!(clipped.ways().contains_key(&-3));
+        }
+    }
+}
+
+pub mod minimap {
+    use sdl2::gfx::primitives::DrawRenderer;
+    use sdl2::pixels::Color;
+    use sdl2::rect::Rect;
+    use sdl2::render::{Canvas, RenderTarget};
+
+    use crate::camera::Camera;
+    use crate::dataset::OsmDataset;
+    use crate::geo::{BBox, LatLon};
+
+    pub const MINIMAP_SIZE: u32 = 180;
+    const MARGIN: i32 = 10;
+
+    /// a whole-dataset overview in the bottom right corner
+    pub struct Minimap {
+        pub camera: Camera,
+        pub x: i32,
+        pub y: i32,
+        /// every way, projected once and thinned to points at least a pixel apart
+        lines: Vec<Vec<(i16, i16)>>,
+    }
+
+    impl Minimap {
+        pub fn new(dataset: &OsmDataset, bbox: &BBox, window_width: u32, window_height: u32) -> Minimap {
+            let camera = Camera::fit_bbox(bbox, MINIMAP_SIZE, MINIMAP_SIZE);
+            let lines = dataset.ways().values()
+                .map(|way| {
+                    let mut line: Vec<(i16, i16)> = Vec::new();
+                    for point in dataset.way_coordinates(way) {
+                        let (x, y) = camera.project(point);
+                        let point = (x as i16, y as i16);
+                        if line.last() != Some(&point) {
+                            line.push(point);
+                        }
+                    }
+                    line
+                })
+                .filter(|line| line.len() >= 2)
+                .collect();
+
+            let mut minimap = Minimap { camera, x: 0, y: 0, lines };
+            minimap.place(window_width, window_height);
+            minimap
+        }
+
+        /// keeps the minimap in the corner when the window size changes
+        pub fn place(&mut self, window_width: u32, window_height: u32) {
+            self.x = window_width as i32 - MINIMAP_SIZE as i32 - MARGIN;
+            self.y = window_height as i32 - MINIMAP_SIZE as i32 - MARGIN;
+        }
+
+        pub fn rect(&self) -> Rect {
+            Rect::new(self.x, self.y, MINIMAP_SIZE, MINIMAP_SIZE)
+        }
+
+        /// the map position under a window pixel inside the minimap
+        pub fn unproject(&self, x: i32, y: i32) -> LatLon {
+            self.camera.unproject(x - self.x, y - self.y)
+        }
+
+        fn project(&self, point: LatLon) -> (i16, i16) {
+            let (x, y) = self.camera.project(point);
+            ((x + self.x) as i16, (y + self.y) as i16)
+        }
+
+        /// `markers` are locations with a color, e.g. the sprites
+        pub fn draw<T: RenderTarget>(&self, canvas: &mut Canvas<T>, camera: &Camera, markers: &[(LatLon, Color)]) {
+            let rect = self.rect();
+            let (left, top) = (rect.left() as i16, rect.top() as i16);
+            let (right, bottom) = (rect.right() as i16, rect.bottom() as i16);
+
+            let _ = canvas.box_(left, top, right, bottom, Color::RGBA(255, 255, 255, 220));
+            for line in &self.lines {
+                for segment in line.windows(2) {
+                    let (x1, y1) = (segment[0].0 + left, segment[0].1 + top);
+                    let (x2, y2) = (segment[1].0 + left, segment[1].1 + top);
+                    if (x1 < left && x2 < left) || (x1 > right && x2 > right) || (y1 < top && y2 < top) || (y1 > bottom && y2 > bottom) {
+                        continue;
+                    }
+                    let _ = canvas.line(x1, y1, x2, y2, Color::RGB(150, 150, 150));
+                }
+            }
+
+            let viewport = camera.viewport_bbox();
+            let (view_left, view_top) = self.project(LatLon::new(viewport.max_lat, viewport.min_lon));
+            let (view_right, view_bottom) = self.project(LatLon::new(viewport.min_lat, viewport.max_lon));
+            let _ = canvas.rectangle(
+                view_left.clamp(left, right), view_top.clamp(top, bottom),
+                view_right.clamp(left, right), view_bottom.clamp(top, bottom),
+                Color::RGB(220, 0, 0),
+            );
+
+            for (location, color) in markers {
+                let (x, y) = self.project(*location);
+                if rect.contains_point((x as i32, y as i32)) {
+                    let _ = canvas.filled_circle(x, y, 2, *color);
+                }
+            }
+
+            let _ = canvas.rectangle(left, top, right, bottom, Color::RGB(0, 0, 0));
+        }
+    }
+}
+
+pub mod overlay {
+    use sdl2::gfx::primitives::DrawRenderer;
+    use sdl2::pixels::Color;
+    use sdl2::render::{Canvas, RenderTarget};
+
+    use crate::camera::Camera;
+    use crate::geo::LatLon;
+
+    const METERS_PER_FOOT: f64 = 0.3048;
+    const METERS_PER_MILE: f64 = 1609.344;
+    /// the scale bar never gets longer than this
+    const MAX_BAR_WIDTH: f64 = 150.0;
+    /// graticule lines are at least this far apart
+    const MIN_GRID_SPACING: f64 = 80.0;
+
+    /// the map furniture drawn on top of everything, each part switched separately
+    pub struct Overlay {
+        pub scale_bar: bool,
+        pub imperial: bool,
+        pub coordinates: bool,
+        pub graticule: bool,
+    }
+
+    impl Default for Overlay {
+        fn default() -> Overlay {
+            Overlay { scale_bar: true, imperial: false, coordinates: true, graticule: false }
+        }
+    }
+
+    impl Overlay {
+        pub fn draw<T: RenderTarget>(&self, canvas: &mut Canvas<T>, camera: &Camera, mouse_x: i32, mouse_y: i32) {
+            if self.graticule {
+                draw_graticule(canvas, camera);
+            }
+
+            let bottom = camera.viewport_height as i16 - 10;
+            if self.scale_bar {
+                self.draw_scale_bar(canvas, camera, 10, bottom);
+            }
+            if self.coordinates {
+                let location = camera.unproject(mouse_x, mouse_y);
+                let text = format!("{:.5}, {:.5}", location.lat, location.lon);
+                let _ = canvas.string(10, bottom - 30, &text, Color::RGB(0, 0, 0));
+            }
+        }
+
+        /// bar at `(x, y)` bottom left, as long as the largest 1-2-5 step that fits
+        fn draw_scale_bar<T: RenderTarget>(&self, canvas: &mut Canvas<T>, camera: &Camera, x: i16, y: i16) {
+            let center_x = camera.viewport_width as i32 / 2;
+            let center_y = camera.viewport_height as i32 / 2;
+            let meters_per_pixel = camera.unproject(center_x, center_y).distance_to(&camera.unproject(center_x + 100, center_y)) / 100.0;
+            if meters_per_pixel <= 0.0 || !meters_per_pixel.is_finite() { return; }
+
+            let max_meters = meters_per_pixel * MAX_BAR_WIDTH;
+            let (unit_meters, small, large, large_meters) = if self.imperial {
+                (METERS_PER_FOOT, "ft", "mi", METERS_PER_MILE)
+            } else {
+                (1.0, "m", "km", 1000.0)
+            };
+            let length = if max_meters >= large_meters {
+                nice_step(max_meters / large_meters) * large_meters
+            } else {
+                nice_step(max_meters / unit_meters) * unit_meters
+            };
+            let label = if length >= large_meters {
+                format!("{} {}", length / large_meters, large)
+            } else {
+                format!("{} {}", (length / unit_meters).round(), small)
+            };
+
+            let width = (length / meters_per_pixel).round() as i16;
+            let color = Color::RGB(0, 0, 0);
+            let _ = canvas.thick_line(x, y, x + width, y, 2, color);
+            let _ = canvas.line(x, y - 5, x, y, color);
+            let _ = canvas.line(x + width, y - 5, x + width, y, color);
+            let _ = canvas.string(x, y - 15, &label, color);
+        }
+    }
+
+    /// largest 1, 2 or 5 times a power of ten not above `value`
+    fn nice_step(value: f64) -> f64 {
+        let magnitude = 10f64.powf(value.log10().floor());
+        [5.0, 2.0, 1.0].iter()
+            .map(|factor| factor * magnitude)
+            .find(|step| *step <= value)
+            .unwrap_or(magnitude)
+    }
+
+    fn draw_graticule<T: RenderTarget>(canvas: &mut Canvas<T>, camera: &Camera) {
+        let bbox = camera.viewport_bbox();
+        let (width, height) = (camera.viewport_width as f64, camera.viewport_height as f64);
+        let lon_step = grid_step((bbox.max_lon - bbox.min_lon) / width * MIN_GRID_SPACING);
+        let lat_step = grid_step((bbox.max_lat - bbox.min_lat) / height * MIN_GRID_SPACING);
+        if lon_step <= 0.0 || lat_step <= 0.0 { return; }
+
+        let color = Color::RGBA(0, 0, 120, 90);
+        let label_color = Color::RGB(0, 0, 120);
+        let decimals = (-lon_step.min(lat_step).log10()).ceil().max(0.0) as usize;
+
+        let mut lon = (bbox.min_lon / lon_step).ceil() * lon_step;
+        while lon <= bbox.max_lon {
+            let (x, _) = camera.project(LatLon::new(bbox.center().lat, lon));
+            let _ = canvas.vline(x as i16, 0, height as i16, color);
+            let _ = canvas.string(x as i16 + 2, 2, &format!("{:.*}", decimals, lon), label_color);
+            lon += lon_step;
+        }
+
+        let mut lat = (bbox.min_lat / lat_step).ceil() * lat_step;
+        while lat <= bbox.max_lat {
+            let (_, y) = camera.project(LatLon::new(lat, bbox.center().lon));
+            let _ = canvas.hline(0, width as i16, y as i16, color);
+            let _ = canvas.string(2, y as i16 + 2, &format!("{:.*}", decimals, lat), label_color);
+            lat += lat_step;
+        }
+    }
+
+    /// smallest 1-2-5 step of degrees at least `min_degrees`, capped at whole-globe spacing
+    fn grid_step(min_degrees: f64) -> f64 {
+        if !min_degrees.is_finite() || min_degrees <= 0.0 { return 0.0; }
+
+        let magnitude = 10f64.powf(min_degrees.log10().floor());
+        [1.0, 2.0, 5.0, 10.0].iter()
+            .map(|factor| factor * magnitude)
+            .find(|step| *step >= min_degrees)
+            .unwrap_or(10.0 * magnitude)
+            .min(30.0)
+    }
+}
+
+pub mod mvt {
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use crate::dataset::OsmDataset;
+    use crate::geo::{BBox, LatLon, TileId};
+    use crate::osm::{Metadata, Node, Relation, RelationMember, Way};
+    use crate::osm_reader::Element;
+    use crate::tiles::TileSource;
+
+    /// file extensions of vector tiles in a tile directory
+    pub const EXTENSIONS: &[&str] = &["mvt", "pbf"];
+
+    /// protobuf wire types used by the vector tile spec
+    const VARINT: u8 = 0;
+    const FIXED64: u8 = 1;
+    const LENGTH_DELIMITED: u8 = 2;
+    const FIXED32: u8 = 5;
+
+    /// just enough of a protobuf decoder for `vector_tile.proto`
+    struct ProtoReader<'a> {
+        data: &'a [u8],
+        position: usize,
+    }
+
+    impl<'a> ProtoReader<'a> {
+        fn new(data: &'a [u8]) -> ProtoReader<'a> {
+            ProtoReader { data, position: 0 }
+        }
+
+        fn next_field(&mut self) -> Result<Option<(u64, u8)>, Box<dyn Error>> {
+            if self.position >= self.data.len() {
+                return Ok(None);
+            }
+            let key = self.varint()?;
+            Ok(Some((key >> 3, (key & 0x7) as u8)))
+        }
+
+        fn varint(&mut self) -> Result<u64, Box<dyn Error>> {
+            let mut value = 0u64;
+            for shift in (0..64).step_by(7) {
+                let byte = *self.data.get(self.position).ok_or("truncated varint")?;
+                self.position += 1;
+                value |= ((byte & 0x7f) as u64) << shift;
+                if byte & 0x80 == 0 {
+                    return Ok(value);
+                }
+            }
+            Err("varint too long".into())
+        }
+
+        fn take(&mut self, length: usize) -> Result<&'a [u8], Box<dyn Error>> {
+            let end = self.position.checked_add(length).filter(|end| *end <= self.data.len()).ok_or("truncated field")?;
+            let bytes = &self.data[self.position..end];
+            self.position = end;
+            Ok(bytes)
+        }
+
+        fn bytes(&mut self) -> Result<&'a [u8], Box<dyn Error>> {
+            let length = self.varint()? as usize;
+            self.take(length)
+        }
+
+        fn string(&mut self) -> Result<String, Box<dyn Error>> {
+            Ok(String::from_utf8_lossy(self.bytes()?).into_owned())
+        }
+
+        fn packed_u32(&mut self) -> Result<Vec<u32>, Box<dyn Error>> {
+            let mut packed = ProtoReader::new(self.bytes()?);
+            let mut values = Vec::new();
+            while packed.position < packed.data.len() {
+                values.push(packed.varint()? as u32);
+            }
+            Ok(values)
+        }
+
+        fn skip(&mut self, wire_type: u8) -> Result<(), Box<dyn Error>> {
+            match wire_type {
+                VARINT => { self.varint()?; }
+                FIXED64 => { self.take(8)?; }
+                LENGTH_DELIMITED => { self.bytes()?; }
+                FIXED32 => { self.take(4)?; }
+                _ => return Err(format!("unsupported wire type {}", wire_type).into()),
+            }
+            Ok(())
+        }
+    }
+
+    /// the points, lines or rings of a feature in tile units
+    pub type Parts = Vec<Vec<(i32, i32)>>;
+
+    fn zigzag(value: u32) -> i32 {
+        ((value >> 1) as i32) ^ -((value & 1) as i32)
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum GeometryType {
+        Unknown,
+        Point,
+        LineString,
+        Polygon,
+    }
+
+    pub struct Feature {
+        pub id: Option<u64>,
+        pub geometry_type: GeometryType,
+        /// see `Layer::extent` for the units
+        pub geometry: Parts,
+        /// every value type is turned into its text form, like osm tags
+        pub attributes: HashMap<String, String>,
+    }
+
+    pub struct Layer {
+        pub name: String,
+        pub extent: u32,
+        pub features: Vec<Feature>,
+    }
+
+    /// decodes a tile, gzip compressed or not
+    pub fn decode_tile(data: &[u8]) -> Result<Vec<Layer>, Box<dyn Error>> {
+        if data.starts_with(&[0x1f, 0x8b]) {
+            let mut inflated = Vec::new();
+            GzDecoder::new(data).read_to_end(&mut inflated)?;
+            return decode_tile(&inflated);
+        }
+
+        let mut reader = ProtoReader::new(data);
+        let mut layers = Vec::new();
+        while let Some((field, wire_type)) = reader.next_field()? {
+            match (field, wire_type) {
+                (3, LENGTH_DELIMITED) => layers.push(decode_layer(reader.bytes()?)?),
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(layers)
+    }
+
+    fn decode_layer(data: &[u8]) -> Result<Layer, Box<dyn Error>> {
+        let mut reader = ProtoReader::new(data);
+        let mut name = String::new();
+        let mut extent = 4096;
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+        let mut raw_features = Vec::new();
+
+        while let Some((field, wire_type)) = reader.next_field()? {
+            match (field, wire_type) {
+                (1, LENGTH_DELIMITED) => name = reader.string()?,
+                (2, LENGTH_DELIMITED) => raw_features.push(reader.bytes()?),
+                (3, LENGTH_DELIMITED) => keys.push(reader.string()?),
+                (4, LENGTH_DELIMITED) => values.push(decode_value(reader.bytes()?)?),
+                (5, VARINT) => extent = reader.varint()? as u32,
+                _ => reader.skip(wire_type)?,
+            }
+        }
+
+        let features = raw_features.into_iter()
+            .map(|data| decode_feature(data, &keys, &values))
+            .collect::<Result<Vec<Feature>, Box<dyn Error>>>()?;
+        Ok(Layer { name, extent, features })
+    }
+
+    fn decode_value(data: &[u8]) -> Result<String, Box<dyn Error>> {
+        let mut reader = ProtoReader::new(data);
+        let mut value = String::new();
+        while let Some((field, wire_type)) = reader.next_field()? {
+            value = match (field, wire_type) {
+                (1, LENGTH_DELIMITED) => reader.string()?,
+                (2, FIXED32) => f32::from_le_bytes(reader.take(4)?.try_into()?).to_string(),
+                (3, FIXED64) => f64::from_le_bytes(reader.take(8)?.try_into()?).to_string(),
+                (4, VARINT) => (reader.varint()? as i64).to_string(),
+                (5, VARINT) => reader.varint()?.to_string(),
+                (6, VARINT) => {
+                    let raw = reader.varint()?;
+                    (((raw >> 1) as i64) ^ -((raw & 1) as i64)).to_string()
+                }
+                (7, VARINT) => (reader.varint()? != 0).to_string(),
+                _ => {
+                    reader.skip(wire_type)?;
+                    continue;
+                }
+            };
+        }
+        Ok(value)
+    }
+
+    fn decode_feature(data: &[u8], keys: &[String], values: &[String]) -> Result<Feature, Box<dyn Error>> {
+        let mut reader = ProtoReader::new(data);
+        let mut feature = Feature { id: None, geometry_type: GeometryType::Unknown, geometry: Vec::new(), attributes: HashMap::new() };
+
+        while let Some((field, wire_type)) = reader.next_field()? {
+            match (field, wire_type) {
+                (1, VARINT) => feature.id = Some(reader.varint()?),
+                (2, LENGTH_DELIMITED) => {
+                    for pair in reader.packed_u32()?.chunks(2) {
+                        if let [key, value] = pair {
+                            let key = keys.get(*key as usize).ok_or("feature tag key out of range")?;
+                            let value = values.get(*value as usize).ok_or("feature tag value out of range")?;
+                            feature.attributes.insert(key.clone(), value.clone());
+                        }
+                    }
+                }
+                (3, VARINT) => {
+                    feature.geometry_type = match reader.varint()? {
+                        1 => GeometryType::Point,
+                        2 => GeometryType::LineString,
+                        3 => GeometryType::Polygon,
+                        _ => GeometryType::Unknown,
+                    }
+                }
+                (4, LENGTH_DELIMITED) => feature.geometry = decode_geometry(&reader.packed_u32()?)?,
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(feature)
+    }
+
+    /// runs the MoveTo / LineTo / ClosePath command stream, every MoveTo starts a new part
+    fn decode_geometry(commands: &[u32]) -> Result<Parts, Box<dyn Error>> {
+        const MOVE_TO: u32 = 1;
+        const LINE_TO: u32 = 2;
+        const CLOSE_PATH: u32 = 7;
+
+        let (mut x, mut y) = (0i32, 0i32);
+        let mut parts = Vec::new();
+        let mut current: Vec<(i32, i32)> = Vec::new();
+        let mut index = 0;
+
+        while index < commands.len() {
+            let (command, count) = (commands[index] & 0x7, commands[index] >> 3);
+            index += 1;
+
+            match command {
+                MOVE_TO | LINE_TO => {
+                    for _ in 0..count {
+                        let (dx, dy) = match commands.get(index..index + 2) {
+                            Some([dx, dy]) => (zigzag(*dx), zigzag(*dy)),
+                            _ => return Err("truncated geometry".into()),
+                        };
+                        index += 2;
+                        x += dx;
+                        y += dy;
+
+                        if command == MOVE_TO && !current.is_empty() {
+                            parts.push(std::mem::take(&mut current));
+                        }
+                        current.push((x, y));
+                    }
+                }
+                CLOSE_PATH => {
+                    if let Some(first) = current.first().copied() {
+                        current.push(first);
+                    }
+                }
+                _ => return Err(format!("unknown geometry command {}", command).into()),
+            }
+        }
+        if !current.is_empty() {
+            parts.push(current);
+        }
+
+        Ok(parts)
+    }
+
+    /// positive for exterior rings, y pointing down as in tile coordinates
+    fn ring_area(ring: &[(i32, i32)]) -> i64 {
+        ring.windows(2).map(|edge| edge[0].0 as i64 * edge[1].1 as i64 - edge[1].0 as i64 * edge[0].1 as i64).sum()
+    }
+
+    /// every feature of the tiles covering `bbox` at `zoom`, as untagged-id osm elements the renderer and stylesheet understand
+    ///
+    /// attributes become tags plus `mvt:layer`; lines and rings become ways with inline geometry, points become nodes,
+    /// polygons with holes become untagged rings under a tagged `type=multipolygon` relation, as in osm. ids are negative since features carry no osm identity
+    pub fn load(source: &TileSource, bbox: &BBox, zoom: u8) -> Result<OsmDataset, Box<dyn Error>> {
+        let mut dataset = OsmDataset::default();
+        let mut next_id = -1;
+        let mut new_id = || {
+            next_id -= 1;
+            next_id + 1
+        };
+
+        for tile in TileId::covering(bbox, zoom) {
+            let Some(data) = source.read(tile)? else { continue };
+
+            for layer in decode_tile(&data)? {
+                let extent = layer.extent as f64;
+                let to_lat_lon = |part: &Vec<(i32, i32)>| -> Vec<LatLon> {
+                    part.iter().map(|(x, y)| tile.location(*x as f64, *y as f64, extent)).collect()
+                };
+
+                for feature in layer.features {
+                    let mut tags = feature.attributes;
+                    tags.insert("mvt:layer".to_string(), layer.name.clone());
+
+                    match feature.geometry_type {
+                        GeometryType::Point => {
+                            for point in feature.geometry.iter().flat_map(&to_lat_lon) {
+                                let id = new_id();
+                                dataset.insert(Element::Node(Node { id, meta: Metadata::default(), lat: point.lat, lon: point.lon, tags: tags.clone() }));
+                            }
+                        }
+                        GeometryType::LineString => {
+                            for part in &feature.geometry {
+                                let way = Way { id: new_id(), meta: Metadata::default(), nodes: Vec::new(), tags: tags.clone(), geometry: Some(to_lat_lon(part)), bounds: None, center: None };
+                                dataset.insert(Element::Way(way));
+                            }
+                        }
+                        GeometryType::Polygon => {
+                            tags.entry("area".to_string()).or_insert_with(|| "yes".to_string());
+                            let has_holes = feature.geometry.iter().any(|ring| ring_area(ring) < 0);
+                            let mut members = Vec::new();
+
+                            for ring in &feature.geometry {
+                                let exterior = ring_area(ring) > 0;
+                                let ring_tags = if has_holes { HashMap::new() } else { tags.clone() };
+                                let id = new_id();
+                                let geometry = to_lat_lon(ring);
+                                members.push(RelationMember {
+                                    id,
+                                    role: if exterior { "outer" } else { "inner" }.to_string(),
+                                    element_type: "way".to_string(),
+                                    location: None,
+                                    geometry: Some(geometry.clone()),
+                                });
+                                dataset.insert(Element::Way(Way { id, meta: Metadata::default(), nodes: Vec::new(), tags: ring_tags, geometry: Some(geometry), bounds: None, center: None }));
+                            }
+
+                            if has_holes {
+                                let mut relation_tags = tags.clone();
+                                relation_tags.insert("type".to_string(), "multipolygon".to_string());
+                                let relation = Relation { id: new_id(), meta: Metadata::default(), members, tags: relation_tags, bounds: None, center: None };
+                                dataset.insert(Element::Relation(relation));
+                            }
+                        }
+                        GeometryType::Unknown => {}
+                    }
+                }
+            }
+        }
+
+        Ok(dataset)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use sdl2::pixels::Color;
+
+        use super::*;
+        use crate::camera::Camera;
+        use crate::geojson::multipolygon;
+        use crate::map::even_odd_spans;
+        use crate::style::StyleSheet;
+
+        fn decode(commands: &[u32]) -> Parts {
+            decode_geometry(commands).unwrap()
+        }
+
+        /// a length delimited protobuf field
+        fn field(number: u8, bytes: &[u8]) -> Vec<u8> {
+            let mut encoded = vec![number << 3 | LENGTH_DELIMITED, bytes.len() as u8];
+            encoded.extend_from_slice(bytes);
+            encoded
+        }
+
+        #[test]
+        fn zigzag_alternates_signs() {
+            assert_eq!([0, 1, 2, 3, 4].map(zigzag), [0, -1, 1, -2, 2]);
+            assert_eq!(zigzag(u32::MAX), i32::MIN);
+            assert_eq!(zigzag(u32::MAX - 1), i32::MAX);
+        }
+
+        #[test]
+        fn reads_multi_byte_and_packed_varints() {
+            assert_eq!(ProtoReader::new(&[0xac, 0x02]).varint().unwrap(), 300);
+            assert_eq!(ProtoReader::new(&[0x05, 0x96, 0x01, 0x05, 0xac, 0x02]).packed_u32().unwrap(), vec![150, 5, 300]);
+            assert!(ProtoReader::new(&[0x96]).varint().is_err());
+            assert!(ProtoReader::new(&[0x05, 0x96, 0x01]).packed_u32().is_err());
+        }
+
+        // the examples from section 4.3.5 of the vector tile spec
+
+        #[test]
+        fn decodes_a_point() {
+            assert_eq!(decode(&[9, 50, 34]), vec![vec![(25, 17)]]);
+        }
+
+        #[test]
+        fn decodes_a_multipoint_as_one_part_per_point() {
+            assert_eq!(decode(&[17, 10, 14, 3, 9]), vec![vec![(5, 7)], vec![(3, 2)]]);
+        }
+
+        #[test]
+        fn decodes_a_linestring() {
+            assert_eq!(decode(&[9, 4, 4, 18, 0, 16, 16, 0]), vec![vec![(2, 2), (2, 10), (10, 10)]]);
+        }
+
+        #[test]
+        fn keeps_the_cursor_across_parts() {
+            let parts = decode(&[9, 4, 4, 18, 0, 16, 16, 0, 9, 17, 17, 10, 4, 8]);
+            assert_eq!(parts, vec![vec![(2, 2), (2, 10), (10, 10)], vec![(1, 1), (3, 5)]]);
+        }
+
+        #[test]
+        fn close_path_repeats_the_first_point() {
+            assert_eq!(decode(&[9, 6, 12, 18, 10, 12, 24, 44, 15]), vec![vec![(3, 6), (8, 12), (20, 34), (3, 6)]]);
+        }
+
+        #[test]
+        fn tells_exterior_rings_from_holes() {
+            let parts = decode(&[
+                9, 0, 0, 26, 20, 0, 0, 20, 19, 0, 15,
+                9, 22, 2, 26, 18, 0, 0, 18, 17, 0, 15,
+                9, 4, 13, 26, 0, 8, 8, 0, 0, 7, 15,
+            ]);
+            assert_eq!(parts, vec![
+                vec![(0, 0), (10, 0), (10, 10), (0, 10), (0, 0)],
+                vec![(11, 11), (20, 11), (20, 20), (11, 20), (11, 11)],
+                vec![(13, 13), (13, 17), (17, 17), (17, 13), (13, 13)],
+            ]);
+            assert_eq!(parts.iter().map(|ring| ring_area(ring).signum()).collect::<Vec<_>>(), vec![1, 1, -1]);
+        }
+
+        #[test]
+        fn rejects_broken_command_streams() {
+            assert!(decode_geometry(&[9, 50]).is_err());
+            assert!(decode_geometry(&[3, 0, 0]).is_err());
+        }
+
+        #[test]
+        fn decodes_a_layer_with_attributes() {
+            let value = field(1, b"primary");
+            let feature = [
+                vec![1 << 3 | VARINT, 42],
+                field(2, &[0, 0]),
+                vec![3 << 3 | VARINT, 2],
+                field(4, &[9, 4, 4, 18, 0, 16, 16, 0]),
+            ].concat();
+            let layer = [field(1, b"roads"), field(2, &feature), field(3, b"highway"), field(4, &value), vec![5 << 3 | VARINT, 0x80, 0x20]].concat();
+
+            let layers = decode_tile(&field(3, &layer)).unwrap();
+            assert_eq!(layers.len(), 1);
+            assert_eq!((layers[0].name.as_str(), layers[0].extent), ("roads", 4096));
+            let feature = &layers[0].features[0];
+            assert_eq!((feature.id, feature.geometry_type), (Some(42), GeometryType::LineString));
+            assert_eq!(feature.attributes.get("highway").map(|value| value.as_str()), Some("primary"));
+            assert_eq!(feature.geometry, vec![vec![(2, 2), (2, 10), (10, 10)]]);
+        }
+
+        #[test]
+        fn renders_holed_polygons_with_their_style() {
+            let geometry = [9, 4, 4, 26, 24, 0, 0, 24, 23, 0, 15, 9, 8, 15, 26, 0, 8, 8, 0, 0, 7, 15];
+            let feature = [field(2, &[0, 0]), vec![3 << 3 | VARINT, 3], field(4, &geometry)].concat();
+            let layer = [field(1, b"water"), field(2, &feature), field(3, b"natural"), field(4, &field(1, b"water")), vec![5 << 3 | VARINT, 16]].concat();
+            let root = std::env::temp_dir().join(format!("mvt-holes-{}", std::process::id()));
+            std::fs::create_dir_all(root.join("0/0")).unwrap();
+            std::fs::write(root.join("0/0/0.mvt"), field(3, &layer)).unwrap();
+
+            let source = TileSource::open(root.to_str().unwrap(), EXTENSIONS).unwrap();
+            let dataset = load(&source, &BBox::new(-80.0, -170.0, 80.0, 170.0), 0).unwrap();
+            std::fs::remove_dir_all(&root).unwrap();
+
+            let relation = dataset.relations().values().next().unwrap();
+            assert!(dataset.ways().values().all(|way| way.tags.is_empty()));
+            assert_eq!(StyleSheet::default().style_for(&relation.tags).fill, Some(Color::RGB(170, 211, 223)));
+
+            // at zoom 1 the world is 512 pixels across, so tile units are 32 pixels
+            let camera = Camera::new(LatLon::new(0.0, 0.0), 1.0, 512, 512);
+            let polygons = multipolygon(relation, &dataset);
+            assert_eq!(polygons.len(), 1);
+            let rings: Vec<Vec<(i32, i32)>> = polygons[0].iter()
+                .map(|ring| ring.iter().map(|point| camera.project(*point)).collect())
+                .collect();
+            let spans = even_odd_spans(&rings, 512);
+            let row = |y: i32| spans.iter().filter(|span| span.0 == y).map(|span| (span.1, span.2)).collect::<Vec<_>>();
+            assert_eq!(row(100), vec![(64, 447)]);
+            assert_eq!(row(256), vec![(64, 191), (320, 447)]);
+            assert_eq!(row(500), vec![]);
+        }
+    }
+}
+
+pub mod tiles {
+    use std::error::Error;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use rusqlite::{Connection, OpenFlags, OptionalExtension};
+
+    use crate::geo::TileId;
+
+    /// where tiles come from: `{z}/{x}/{y}.<extension>` files under a directory, or an mbtiles file
+    pub enum TileSource {
+        Directory { root: PathBuf, extensions: &'static [&'static str] },
+        MBTiles(Connection),
+    }
+
+    impl TileSource {
+        /// `extensions` are tried in order when `path` is a directory
+        pub fn open(path: &str, extensions: &'static [&'static str]) -> Result<TileSource, Box<dyn Error>> {
+            if Path::new(path).is_dir() {
+                Ok(TileSource::Directory { root: PathBuf::from(path), extensions })
+            } else {
+                Ok(TileSource::MBTiles(Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?))
+            }
+        }
+
+        /// `None` when the source has no such tile
+        pub fn read(&self, tile: TileId) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+            match self {
+                TileSource::Directory { root, extensions } => {
+                    for extension in extensions.iter() {
+                        let path = root.join(tile.z.to_string()).join(tile.x.to_string()).join(format!("{}.{}", tile.y, extension));
+                        if path.exists() {
+                            return Ok(Some(fs::read(path)?));
+                        }
+                    }
+                    Ok(None)
+                }
+                TileSource::MBTiles(connection) => {
+                    // mbtiles rows count from the south (tms)
+                    let row = (1u32 << tile.z) - 1 - tile.y;
+                    let data = connection
+                        .query_row(
+                            "SELECT tile_data FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+                            (tile.z, tile.x, row),
+                            |row| row.get(0),
+                        )
+                        .optional()?;
+                    Ok(data)
+                }
+            }
+        }
+    }
+}
+
+pub mod basemap {
+    use std::collections::{HashMap, HashSet};
+    use std::error::Error;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::thread;
+
+    use sdl2::pixels::PixelFormatEnum;
+    use sdl2::rect::Rect;
+    use sdl2::render::{Canvas, RenderTarget, Texture, TextureCreator};
+
+    use crate::camera::{Camera, MAX_ZOOM};
+    use crate::geo::TileId;
+    use crate::tiles::TileSource;
+
+    /// file extensions of raster tiles in a tile directory
+    pub const EXTENSIONS: &[&str] = &["png"];
+    /// textures kept around before the least recently drawn ones are dropped
+    pub const TEXTURE_CAPACITY: usize = 256;
+
+    /// a decoded tile, rgba with 4 bytes per pixel
+    pub struct TileImage {
+        pub width: u32,
+        pub height: u32,
+        pub pixels: Vec<u8>,
+    }
+
+    pub fn decode_png(data: &[u8]) -> Result<TileImage, Box<dyn Error>> {
+        let mut decoder = png::Decoder::new(data);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+        let buffer = &buffer[..info.buffer_size()];
+
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buffer.to_vec(),
+            png::ColorType::Rgb => buffer.chunks(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect(),
+            png::ColorType::GrayscaleAlpha => buffer.chunks(2).flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]]).collect(),
+            png::ColorType::Grayscale => buffer.iter().flat_map(|g| [*g, *g, *g, 255]).collect(),
+            png::ColorType::Indexed => return Err("indexed png was not expanded".into()),
+        };
+
+        Ok(TileImage { width: info.width, height: info.height, pixels })
+    }
+
+    struct CachedTile<'a> {
+        texture: Texture<'a>,
+        size: u32,
+        last_drawn: u64,
+    }
+
+    /// slippy map png tiles drawn under the vector data
+    ///
+    /// tiles are read and decoded on a worker thread and turned into textures on the main thread,
+    /// while one is loading the closest loaded ancestor is drawn scaled up in its place
+    pub struct Basemap<'a> {
+        requests: Sender<TileId>,
+        results: Receiver<(TileId, Result<Option<TileImage>, String>)>,
+        textures: HashMap<TileId, CachedTile<'a>>,
+        pending: HashSet<TileId>,
+        /// tiles the source does not have, their ancestors get requested instead
+        missing: HashSet<TileId>,
+        frame: u64,
+    }
+
+    impl<'a> Basemap<'a> {
+        pub fn new(source: TileSource) -> Basemap<'a> {
+            let (requests, worker_requests) = channel::<TileId>();
+            let (worker_results, results) = channel();
+
+            thread::spawn(move || {
+                for tile in worker_requests {
+                    let image = match source.read(tile) {
+                        Ok(Some(data)) => decode_png(&data).map(Some),
+                        Ok(None) => Ok(None),
+                        Err(err) => Err(err),
+                    };
+                    if worker_results.send((tile, image.map_err(|err| err.to_string()))).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            Basemap { requests, results, textures: HashMap::new(), pending: HashSet::new(), missing: HashSet::new(), frame: 0 }
+        }
+
+        /// turns whatever the worker finished since the last frame into textures
+        pub fn update<T>(&mut self, texture_creator: &'a TextureCreator<T>) {
+            while let Ok((tile, image)) = self.results.try_recv() {
+                self.pending.remove(&tile);
+
+                match image {
+                    Ok(Some(image)) => match upload(texture_creator, &image) {
+                        Ok(texture) => {
+                            self.textures.insert(tile, CachedTile { texture, size: image.width, last_drawn: self.frame });
+                        }
+                        Err(err) => {
+                            eprintln!("Error: tile {}/{}/{}: {}", tile.z, tile.x, tile.y, err);
+                            self.missing.insert(tile);
+                        }
+                    },
+                    Ok(None) => { self.missing.insert(tile); }
+                    Err(err) => {
+                        eprintln!("Error: tile {}/{}/{}: {}", tile.z, tile.x, tile.y, err);
+                        self.missing.insert(tile);
+                    }
+                }
+            }
+
+            while self.textures.len() > TEXTURE_CAPACITY {
+                let oldest = self.textures.iter().min_by_key(|(_, cached)| cached.last_drawn).map(|(tile, _)| *tile);
+                if let Some(tile) = oldest {
+                    self.textures.remove(&tile);
+                }
+            }
+        }
+
+        pub fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, camera: &Camera) {
+            self.frame += 1;
+            let zoom = camera.zoom.round().clamp(0.0, MAX_ZOOM) as u8;
+
+            for tile in TileId::covering(&camera.viewport_bbox(), zoom) {
+                // something coarser first, to stand in until the tile itself arrives
+                if let (false, Some(parent)) = (self.textures.contains_key(&tile), tile.parent()) {
+                    self.request(parent);
+                }
+                self.request(tile);
+
+                let (left, top) = camera.project(tile.location(0.0, 0.0, 1.0));
+                let (right, bottom) = camera.project(tile.location(1.0, 1.0, 1.0));
+                let target = Rect::new(left, top, (right - left).max(1) as u32, (bottom - top).max(1) as u32);
+
+                let mut ancestor = Some(tile);
+                while let Some(candidate) = ancestor {
+                    if let Some(cached) = self.textures.get_mut(&candidate) {
+                        cached.last_drawn = self.frame;
+                        // the part of the ancestor covering `tile`
+                        let levels = tile.z - candidate.z;
+                        let size = cached.size >> levels;
+                        let mask = (1 << levels) - 1;
+                        let source = Rect::new(((tile.x & mask) * size) as i32, ((tile.y & mask) * size) as i32, size.max(1), size.max(1));
+                        let _ = canvas.copy(&cached.texture, source, target);
+                        break;
+                    }
+                    ancestor = candidate.parent();
+                }
+            }
+        }
+
+        /// asks for `tile`, or for its closest ancestor the source might have
+        fn request(&mut self, tile: TileId) {
+            let mut candidate = Some(tile);
+            while let Some(tile) = candidate {
+                if !self.missing.contains(&tile) {
+                    if !self.textures.contains_key(&tile) && self.pending.insert(tile) {
+                        let _ = self.requests.send(tile);
+                    }
+                    return;
+                }
+                candidate = tile.parent();
+            }
+        }
+    }
+
+    fn upload<'a, T>(texture_creator: &'a TextureCreator<T>, image: &TileImage) -> Result<Texture<'a>, Box<dyn Error>> {
+        let mut texture = texture_creator.create_texture_static(PixelFormatEnum::RGBA32, image.width, image.height)?;
+        texture.update(None, &image.pixels, image.width as usize * 4)?;
+        Ok(texture)
+    }
+}
+
+pub mod gpx {
+    use std::error::Error;
+    use std::fs::File;
+    use std::io::{BufReader, Read};
+
+    use sdl2::gfx::primitives::DrawRenderer;
+    use sdl2::pixels::Color;
+    use sdl2::render::{Canvas, RenderTarget};
+    use xml::reader::{EventReader, XmlEvent};
+
+    use crate::camera::Camera;
+    use crate::geo::LatLon;
+    use crate::map::clamp;
+
+    /// used to time points that have no `<time>`, meters per second
+    pub const WALKING_SPEED: f64 = 1.4;
+
+    #[derive(Clone, Debug)]
+    pub struct GpxPoint {
+        pub location: LatLon,
+        pub elevation: Option<f64>,
+        /// seconds since the unix epoch
+        pub time: Option<f64>,
+        pub name: Option<String>,
+    }
+
+    /// a `<trk>` with its `<trkseg>`s, or a `<rte>` as a single segment
+    #[derive(Clone, Debug, Default)]
+    pub struct Track {
+        pub name: Option<String>,
+        pub segments: Vec<Vec<GpxPoint>>,
+    }
+
+    impl Track {
+        pub fn points(&self) -> impl Iterator<Item = &GpxPoint> {
+            self.segments.iter().flatten()
+        }
+    }
+
+    #[derive(Debug, Default)]
+    pub struct Gpx {
+        pub tracks: Vec<Track>,
+        pub routes: Vec<Track>,
+        pub waypoints: Vec<GpxPoint>,
+    }
+
+    pub fn read_file(path: &str) -> Result<Gpx, Box<dyn Error>> {
+        read_gpx(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read_gpx<R: Read>(source: R) -> Result<Gpx, Box<dyn Error>> {
+        let mut gpx = Gpx::default();
+        let mut track: Option<Track> = None;
+        let mut point: Option<GpxPoint> = None;
+        let mut text = String::new();
+
+        for event in EventReader::new(source) {
+            match event? {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    text.clear();
+                    let number = |key: &str| attributes.iter()
+                        .find(|attribute| attribute.name.local_name == key)
+                        .and_then(|attribute| attribute.value.parse::<f64>().ok());
+
+                    match name.local_name.as_str() {
+                        "trk" | "rte" => track = Some(Track::default()),
+                        "trkseg" => {
+                            if let Some(track) = &mut track {
+                                track.segments.push(Vec::new());
+                            }
+                        }
+                        "trkpt" | "rtept" | "wpt" => {
+                            let (lat, lon) = number("lat").zip(number("lon")).ok_or(format!("<{}> without lat/lon", name.local_name))?;
+                            point = Some(GpxPoint { location: LatLon::new(lat, lon), elevation: None, time: None, name: None });
+                        }
+                        _ => {}
+                    }
+                }
+                XmlEvent::Characters(characters) | XmlEvent::CData(characters) => text.push_str(&characters),
+                XmlEvent::EndElement { name } => {
+                    match (name.local_name.as_str(), &mut point, &mut track) {
+                        ("ele", Some(point), _) => point.elevation = text.trim().parse().ok(),
+                        ("time", Some(point), _) => point.time = parse_timestamp(text.trim()),
+                        ("name", Some(point), _) => point.name = Some(text.trim().to_string()),
+                        ("name", None, Some(track)) => track.name = Some(text.trim().to_string()),
+                        ("trkpt", _, Some(track)) => {
+                            if track.segments.is_empty() {
+                                track.segments.push(Vec::new());
+                            }
+                            track.segments.last_mut().unwrap().extend(point.take());
+                        }
+                        ("rtept", _, Some(track)) => {
+                            if track.segments.is_empty() {
+                                track.segments.push(Vec::new());
+                            }
+                            track.segments[0].extend(point.take());
+                        }
+                        ("wpt", _, _) => gpx.waypoints.extend(point.take()),
+                        ("trk", _, _) => gpx.tracks.extend(track.take()),
+                        ("rte", _, _) => gpx.routes.extend(track.take()),
+                        _ => {}
+                    }
+                    text.clear();
+                }
+                _ => {}
+            }
+        }
+
+        Ok(gpx)
+    }
+
+    /// `2024-05-01T10:15:30Z`, `2024-05-01T10:15:30.250+02:00` and the like, as seconds since the unix epoch
+    pub fn parse_timestamp(value: &str) -> Option<f64> {
+        let (date, time) = value.split_once(['T', ' '])?;
+        let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+        let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+
+        let (time, offset) = match time.find(['Z', 'z', '+', '-']) {
+            Some(index) => (&time[..index], &time[index..]),
+            None => (time, ""),
+        };
+        let offset_seconds = match offset.split_at(offset.len().min(1)) {
+            ("+" | "-", rest) => {
+                let (hours, minutes) = rest.split_once(':').unwrap_or((rest.get(..2)?, rest.get(2..).unwrap_or("0")));
+                let seconds = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().unwrap_or(0) * 60;
+                if offset.starts_with('-') { -seconds } else { seconds }
+            }
+            _ => 0,
+        };
+
+        let mut time = time.splitn(3, ':');
+        let hours = time.next()?.parse::<i64>().ok()?;
+        let minutes = time.next()?.parse::<i64>().ok()?;
+        let seconds = time.next().unwrap_or("0").parse::<f64>().ok()?;
+
+        let seconds_of_day = (hours * 3600 + minutes * 60 - offset_seconds) as f64 + seconds;
+        Some(days_from_civil(year, month, day) as f64 * 86400.0 + seconds_of_day)
+    }
+
+    /// days since 1970-01-01 in the proleptic gregorian calendar
+    fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+        era * 146097 + day_of_era - 719468
+    }
+
+    /// a position moving along a track as time passes
+    ///
+    /// the track's own timestamps are used when every point has one and they never go backwards, otherwise it is walked at `WALKING_SPEED`
+    pub struct Playback {
+        points: Vec<LatLon>,
+        /// seconds from the start of the track
+        times: Vec<f64>,
+        /// meters from the start of the track
+        distances: Vec<f64>,
+        /// seconds into the track
+        pub elapsed: f64,
+        /// track seconds per real second
+        pub time_scale: f64,
+        pub paused: bool,
+    }
+
+    impl Playback {
+        /// `None` for tracks without points
+        pub fn new(track: &Track, time_scale: f64) -> Option<Playback> {
+            let points: Vec<&GpxPoint> = track.points().collect();
+            let first = points.first()?;
+
+            let mut distances = vec![0.0];
+            for pair in points.windows(2) {
+                distances.push(distances.last().unwrap() + pair[0].location.distance_to(&pair[1].location));
+            }
+            let times = match points.iter().map(|point| point.time).collect::<Option<Vec<f64>>>() {
+                Some(times) if times.windows(2).all(|pair| pair[0] <= pair[1]) => times.iter().map(|time| time - first.time.unwrap()).collect(),
+                _ => distances.iter().map(|distance| distance / WALKING_SPEED).collect(),
+            };
+
+            Some(Playback { points: points.iter().map(|point| point.location).collect(), times, distances, elapsed: 0.0, time_scale, paused: false })
+        }
+
+        pub fn duration(&self) -> f64 {
+            *self.times.last().unwrap()
+        }
+
+        /// moves on by `seconds` of real time
+        pub fn advance(&mut self, seconds: f64) {
+            if self.paused { return; }
+            self.elapsed = (self.elapsed + seconds * self.time_scale).min(self.duration()).max(0.0);
+        }
+
+        /// the segment the playback is on and how far along it, 0 to 1
+        fn segment(&self) -> (usize, f64) {
+            if self.points.len() < 2 {
+                return (0, 0.0);
+            }
+            let index = self.times.partition_point(|time| *time <= self.elapsed).clamp(1, self.times.len() - 1);
+
+            let (start, end) = (self.times[index - 1], self.times[index]);
+            let fraction = if end > start { ((self.elapsed - start) / (end - start)).clamp(0.0, 1.0) } else { 1.0 };
+            (index - 1, fraction)
+        }
+
+        pub fn position(&self) -> LatLon {
+            let (index, fraction) = self.segment();
+            match self.points.get(index + 1) {
+                Some(next) => {
+                    let current = self.points[index];
+                    LatLon::new(current.lat + (next.lat - current.lat) * fraction, current.lon + (next.lon - current.lon) * fraction)
+                }
+                None => self.points[index],
+            }
+        }
+
+        /// meters covered so far
+        pub fn distance(&self) -> f64 {
+            let (index, fraction) = self.segment();
+            match self.distances.get(index + 1) {
+                Some(next) => self.distances[index] + (next - self.distances[index]) * fraction,
+                None => self.distances[index],
+            }
+        }
+
+        /// meters per second on the current segment
+        pub fn speed(&self) -> f64 {
+            let (index, _) = self.segment();
+            match (self.times.get(index + 1), self.distances.get(index + 1)) {
+                (Some(time), Some(distance)) if *time > self.times[index] => (distance - self.distances[index]) / (time - self.times[index]),
+                _ => 0.0,
+            }
+        }
+
+        /// speed, distance and time scale in the top right corner
+        pub fn draw_hud<T: RenderTarget>(&self, canvas: &mut Canvas<T>, camera: &Camera) {
+            let x = camera.viewport_width as i16 - 190;
+            let color = Color::RGB(0, 0, 0);
+            let state = if self.paused { "paused".to_string() } else { format!("x{}", self.time_scale) };
+
+            let _ = canvas.box_(x - 4, 6, x + 180, 46, Color::RGBA(255, 255, 255, 200));
+            let _ = canvas.string(x, 10, &format!("speed    {:.1} km/h", self.speed() * 3.6), color);
+            let _ = canvas.string(x, 22, &format!("distance {:.2} km", self.distance() / 1000.0), color);
+            let _ = canvas.string(x, 34, &format!("time     {} {}", format_duration(self.elapsed), state), color);
+        }
+    }
+
+    fn format_duration(seconds: f64) -> String {
+        let seconds = seconds as u64;
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    }
+
+    /// tracks and routes as lines, waypoints as labelled dots
+    pub fn draw_gpx<T: RenderTarget>(canvas: &mut Canvas<T>, camera: &Camera, gpx: &Gpx) {
+        let track_color = Color::RGB(220, 30, 160);
+        let route_color = Color::RGB(30, 90, 220);
+
+        for (lines, color) in [(&gpx.tracks, track_color), (&gpx.routes, route_color)] {
+            for segment in lines.iter().flat_map(|track| &track.segments) {
+                let points: Vec<(i32, i32)> = segment.iter().map(|point| camera.project(point.location)).collect();
+                for pair in points.windows(2) {
+                    let _ = canvas.thick_line(clamp(pair[0].0), clamp(pair[0].1), clamp(pair[1].0), clamp(pair[1].1), 3, color);
+                }
+            }
+        }
+
+        for waypoint in &gpx.waypoints {
+            let (x, y) = camera.project(waypoint.location);
+            let _ = canvas.filled_circle(clamp(x), clamp(y), 4, route_color);
+            if let Some(name) = &waypoint.name {
+                let _ = canvas.string(clamp(x + 6), clamp(y - 4), name, Color::RGB(0, 0, 0));
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const MAY_DAY: f64 = 1714558530.0;
+
+        #[test]
+        fn parses_utc_timestamps() {
+            assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0.0));
+            assert_eq!(parse_timestamp("2024-05-01T10:15:30Z"), Some(MAY_DAY));
+            assert_eq!(parse_timestamp("2024-05-01T10:15:30z"), Some(MAY_DAY));
+            assert_eq!(parse_timestamp("2024-05-01 10:15:30"), Some(MAY_DAY));
+            assert_eq!(parse_timestamp("1969-12-31T23:59:59Z"), Some(-1.0));
+        }
+
+        #[test]
+        fn keeps_fractional_seconds() {
+            assert_eq!(parse_timestamp("2024-05-01T10:15:30.250Z"), Some(MAY_DAY + 0.25));
+            assert_eq!(parse_timestamp("2024-05-01T10:15:30.5+02:00"), Some(MAY_DAY - 7200.0 + 0.5));
+        }
+
+        #[test]
+        fn applies_offsets() {
+            assert_eq!(parse_timestamp("2024-05-01T12:15:30+02:00"), Some(MAY_DAY));
+            assert_eq!(parse_timestamp("2024-05-01T12:15:30+0200"), Some(MAY_DAY));
+            assert_eq!(parse_timestamp("2024-05-01T04:45:30-05:30"), Some(MAY_DAY));
+            assert_eq!(parse_timestamp("2024-05-01T00:15:30-10"), Some(MAY_DAY));
+        }
+
+        #[test]
+        fn counts_leap_days() {
+            assert_eq!(days_from_civil(2000, 2, 29), 11016);
+            assert_eq!(days_from_civil(2000, 3, 1), 11017);
+            assert_eq!(days_from_civil(1900, 2, 28), -25509);
+            assert_eq!(days_from_civil(1900, 3, 1), -25508);
+            assert_eq!(days_from_civil(2024, 3, 1) - days_from_civil(2024, 2, 28), 2);
+            assert_eq!(days_from_civil(2023, 3, 1) - days_from_civil(2023, 2, 28), 1);
+        }
+
+        #[test]
+        fn rejects_malformed_timestamps() {
+            assert_eq!(parse_timestamp("2024-05-01"), None);
+            assert_eq!(parse_timestamp("2024-05T10:15:30Z"), None);
+            assert_eq!(parse_timestamp("yesterday at noon"), None);
+            assert_eq!(parse_timestamp("2024-05-01T10Z"), None);
+        }
+
+        #[test]
+        fn walks_tracks_whose_times_go_backwards() {
+            let gpx = read_gpx(r#"<gpx><trk><trkseg>
+                <trkpt lat="0" lon="0"><time>2024-05-01T10:15:30Z</time></trkpt>
+                <trkpt lat="0" lon="0.001"><time>2024-05-01T10:20:30Z</time></trkpt>
+                <trkpt lat="0" lon="0.002"><time>2024-05-01T10:10:30Z</time></trkpt>
+            </trkseg></trk></gpx>"#.as_bytes()).unwrap();
+            let mut playback = Playback::new(&gpx.tracks[0], 1.0).unwrap();
+
+            let length = playback.distances[2];
+            assert!((playback.duration() - length / WALKING_SPEED).abs() < 1e-9);
+
+            playback.advance(1e9);
+            assert_eq!(playback.elapsed, playback.duration());
+            assert_eq!(playback.position().lon, 0.002);
+            playback.advance(-1e9);
+            assert_eq!(playback.elapsed, 0.0);
+        }
+    }
+}
+
+pub mod geofence {
+    use std::collections::{HashMap, HashSet};
+    use std::error::Error;
+    use std::fs;
+
+    use serde_json::Value;
+
+    use crate::dataset::OsmDataset;
+    use crate::geo::{distance_to_segment, point_in_polygon, BBox, LatLon};
+    use crate::geojson::multipolygon;
+    use crate::osm::TagFilter;
+
+    /// streets further away than this do not count as the one a sprite is on, in meters
+    pub const STREET_DISTANCE: f64 = 25.0;
+
+    /// an area sprites can be inside of
+    pub struct Zone {
+        pub name: String,
+        pub tags: HashMap<String, String>,
+        /// outer ring first, then its holes
+        pub polygons: Vec<Vec<Vec<LatLon>>>,
+        pub bbox: BBox,
+    }
+
+    impl Zone {
+        /// `None` when there is no ring to be inside of
+        pub fn new(name: String, tags: HashMap<String, String>, polygons: Vec<Vec<Vec<LatLon>>>) -> Option<Zone> {
+            let polygons: Vec<Vec<Vec<LatLon>>> = polygons.into_iter().filter(|polygon| !polygon.is_empty()).collect();
+            let bbox = BBox::from_points(polygons.iter().flatten().flatten())?;
+            Some(Zone { name, tags, polygons, bbox })
+        }
+
+        pub fn contains(&self, point: LatLon) -> bool {
+            self.bbox.contains(point) && self.polygons.iter().any(|polygon| {
+                point_in_polygon(point, &polygon[0]) && !polygon[1..].iter().any(|hole| point_in_polygon(point, hole))
+            })
+        }
+    }
+
+    /// every area of the dataset matching one of `filters`: closed ways and multipolygon relations
+    pub fn zones_from_dataset(dataset: &OsmDataset, filters: &[TagFilter]) -> Vec<Zone> {
+        let matches = |tags: &HashMap<String, String>| filters.iter().any(|filter| filter.matches(tags));
+        let mut zones = Vec::new();
+
+        for way in dataset.ways().values() {
+            if !way.is_closed() || !matches(&way.tags) { continue; }
+
+            let name = element_name(&way.tags, "way", way.id);
+            zones.extend(Zone::new(name, way.tags.clone(), vec![vec![dataset.way_coordinates(way)]]));
+        }
+        for relation in dataset.relations().values() {
+            if relation.tags.get("type").map(|value| value.as_str()) != Some("multipolygon") || !matches(&relation.tags) { continue; }
+
+            let name = element_name(&relation.tags, "relation", relation.id);
+            zones.extend(Zone::new(name, relation.tags.clone(), multipolygon(relation, dataset)));
+        }
+
+        zones
+    }
+
+    fn element_name(tags: &HashMap<String, String>, element_type: &str, id: i64) -> String {
+        tags.get("name").cloned().unwrap_or_else(|| format!("{} {}", element_type, id))
+    }
+
+    /// `Polygon` and `MultiPolygon` features of a geojson file, named by their `name` property
+    pub fn read_zones(path: &str) -> Result<Vec<Zone>, Box<dyn Error>> {
+        let root: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let features = match root["type"].as_str() {
+            Some("FeatureCollection") => root["features"].as_array().cloned().unwrap_or_default(),
+            Some("Feature") => vec![root],
+            _ => return Err(format!("{} is not a geojson feature collection", path).into()),
+        };
+
+        let ring = |ring: &Value| -> Vec<LatLon> {
+            ring.as_array().into_iter().flatten()
+                .filter_map(|position| Some(LatLon::new(position[1].as_f64()?, position[0].as_f64()?)))
+                .collect()
+        };
+        let polygon = |polygon: &Value| -> Vec<Vec<LatLon>> { polygon.as_array().into_iter().flatten().map(ring).collect() };
+
+        let mut zones = Vec::new();
+        for (index, feature) in features.iter().enumerate() {
+            let geometry = &feature["geometry"];
+            let polygons = match geometry["type"].as_str() {
+                Some("Polygon") => vec![polygon(&geometry["coordinates"])],
+                Some("MultiPolygon") => geometry["coordinates"].as_array().into_iter().flatten().map(polygon).collect(),
+                _ => continue,
+            };
+            let tags: HashMap<String, String> = feature["properties"].as_object().into_iter().flatten()
+                .map(|(key, value)| (key.clone(), value.as_str().map(|value| value.to_string()).unwrap_or_else(|| value.to_string())))
+                .collect();
+            let name = tags.get("name").cloned().unwrap_or_else(|| format!("zone {}", index + 1));
+            zones.extend(Zone::new(name, tags, polygons));
+        }
+
+        Ok(zones)
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Crossing {
+        Enter,
+        Exit,
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct ZoneEvent {
+        pub sprite_id: u64,
+        /// index into `Geofence::zones`
+        pub zone: usize,
+        pub zone_name: String,
+        pub crossing: Crossing,
+    }
+
+    pub type Subscriber = Box<dyn FnMut(&ZoneEvent)>;
+
+    /// keeps track of which sprites are in which zones and tells subscribers when that changes
+    #[derive(Default)]
+    pub struct Geofence {
+        pub zones: Vec<Zone>,
+        inside: HashMap<u64, HashSet<usize>>,
+        subscribers: Vec<Subscriber>,
+    }
+
+    impl Geofence {
+        pub fn new(zones: Vec<Zone>) -> Geofence {
+            Geofence { zones, ..Geofence::default() }
+        }
+
+        pub fn subscribe(&mut self, subscriber: Subscriber) {
+            self.subscribers.push(subscriber);
+        }
+
+        /// compares where every sprite is now against the last update
+        ///
+        /// sprites missing from `positions` are taken as gone and exit their zones
+        pub fn update<I: IntoIterator<Item = (u64, LatLon)>>(&mut self, positions: I) -> Vec<ZoneEvent> {
+            let mut events = Vec::new();
+            let mut seen = HashSet::new();
+
+            for (sprite_id, position) in positions {
+                seen.insert(sprite_id);
+                let now: HashSet<usize> = self.zones.iter().enumerate()
+                    .filter(|(_, zone)| zone.contains(position))
+                    .map(|(index, _)| index)
+                    .collect();
+                let before = self.inside.remove(&sprite_id).unwrap_or_default();
+
+                let mut entered: Vec<&usize> = now.difference(&before).collect();
+                let mut exited: Vec<&usize> = before.difference(&now).collect();
+                entered.sort();
+                exited.sort();
+                events.extend(exited.into_iter().map(|zone| self.event(sprite_id, *zone, Crossing::Exit)));
+                events.extend(entered.into_iter().map(|zone| self.event(sprite_id, *zone, Crossing::Enter)));
+
+                self.inside.insert(sprite_id, now);
+            }
+
+            let gone: Vec<u64> = self.inside.keys().filter(|sprite_id| !seen.contains(sprite_id)).copied().collect();
+            for sprite_id in gone {
+                let mut zones: Vec<usize> = self.inside.remove(&sprite_id).unwrap_or_default().into_iter().collect();
+                zones.sort();
+                events.extend(zones.into_iter().map(|zone| self.event(sprite_id, zone, Crossing::Exit)));
+            }
+
+            for event in &events {
+                for subscriber in &mut self.subscribers {
+                    subscriber(event);
+                }
+            }
+
+            events
+        }
+
+        fn event(&self, sprite_id: u64, zone: usize, crossing: Crossing) -> ZoneEvent {
+            ZoneEvent { sprite_id, zone, zone_name: self.zones[zone].name.clone(), crossing }
+        }
+    }
+
+    /// answers "where am I" with the nearest named street, or the smallest named area around a point
+    pub struct Locator {
+        streets: Vec<(String, Vec<LatLon>)>,
+        areas: Vec<(Zone, f64)>,
+    }
+
+    impl Locator {
+        pub fn build(dataset: &OsmDataset) -> Locator {
+            let mut streets = Vec::new();
+            let mut areas = Vec::new();
+
+            for way in dataset.ways().values() {
+                let Some(name) = way.tags.get("name") else { continue };
+                let points = dataset.way_coordinates(way);
+
+                if way.tags.contains_key("highway") && !way.is_area() {
+                    streets.push((name.clone(), points));
+                } else if way.is_area() {
+                    areas.extend(Zone::new(name.clone(), HashMap::new(), vec![vec![points]]));
+                }
+            }
+            for relation in dataset.relations().values() {
+                let Some(name) = relation.tags.get("name") else { continue };
+                if relation.tags.get("type").map(|value| value.as_str()) == Some("multipolygon") {
+                    areas.extend(Zone::new(name.clone(), HashMap::new(), multipolygon(relation, dataset)));
+                }
+            }
+
+            let areas = areas.into_iter()
+                .map(|zone| {
+                    let size = (zone.bbox.max_lat - zone.bbox.min_lat) * (zone.bbox.max_lon - zone.bbox.min_lon);
+                    (zone, size)
+                })
+                .collect();
+            Locator { streets, areas }
+        }
+
+        pub fn describe(&self, point: LatLon) -> Option<String> {
+            let street = self.streets.iter()
+                .flat_map(|(name, points)| points.windows(2).map(move |segment| (name, distance_to_segment(point, segment[0], segment[1]))))
+                .filter(|(_, distance)| *distance <= STREET_DISTANCE)
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((name, _)) = street {
+                return Some(name.clone());
+            }
+
+            self.areas.iter()
+                .filter(|(zone, _)| zone.contains(point))
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(zone, _)| zone.name.clone())
+        }
+    }
+}
+
+pub mod spawn {
+    use std::collections::HashSet;
+    use std::error::Error;
+    use std::fs;
+
+    use serde::Deserialize;
+
+    use crate::dataset::OsmDataset;
+    use crate::geo::LatLon;
+    use crate::osm::TagFilter;
+    use crate::random::shuffle;
+    use crate::sprite::SpriteType;
+
+    /// `{ "filter": "amenity=cafe,shop=*", "sprite_type": "enemy", "count": 5 }`
+    ///
+    /// places up to `count` sprites at randomly picked tagged nodes matching any of the comma separated filters
+    #[derive(Deserialize)]
+    pub struct SpawnRule {
+        pub filter: String,
+        pub sprite_type: SpriteType,
+        pub count: usize,
+    }
+
+    /// a json array of `SpawnRule`s
+    pub fn read_rules(path: &str) -> Result<Vec<SpawnRule>, Box<dyn Error>> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn default_rules() -> Vec<SpawnRule> {
+        let rule = |filter: &str, sprite_type: SpriteType, count: usize| SpawnRule { filter: filter.to_string(), sprite_type, count };
+
+        vec![
+            rule("amenity=cafe,amenity=restaurant,amenity=bar,shop=*", SpriteType::ENEMY, 10),
+            rule("highway=bus_stop,public_transport=platform", SpriteType::COLLECTIBLE, 5),
+        ]
+    }
+
+    /// where each rule's sprites go, in rule order; a node is used by one sprite at most
+    pub fn placements(dataset: &OsmDataset, rules: &[SpawnRule]) -> Vec<(SpriteType, LatLon)> {
+        let mut taken = HashSet::new();
+        let mut placements = Vec::new();
+
+        for rule in rules {
+            let filters = TagFilter::parse_list(&rule.filter);
+            let mut candidates: Vec<(i64, LatLon)> = dataset.nodes().values()
+                .filter(|node| !taken.contains(&node.id) && filters.iter().any(|filter| filter.matches(&node.tags)))
+                .map(|node| (node.id, node.location()))
+                .collect();
+            shuffle(&mut candidates);
+
+            for (id, location) in candidates.into_iter().take(rule.count) {
+                taken.insert(id);
+                placements.push((rule.sprite_type, location));
+            }
+        }
+
+        placements
+    }
+}
+
+pub mod lod {
+    use std::collections::HashMap;
+
+    use crate::camera::TILE_SIZE;
+    use crate::dataset::OsmDataset;
+    use crate::geo::{BBox, LatLon, Projection};
+
+    /// each level is simplified for zooms from its own up to the next one, above the last one ways are drawn in full
+    pub const LEVEL_ZOOMS: [f64; 5] = [8.0, 10.0, 12.0, 14.0, 16.0];
+    /// how far a simplified line may stray from the original, in pixels
+    pub const TOLERANCE_PIXELS: f64 = 1.0;
+    /// ways whose bounds stay under this many pixels across are left out
+    pub const MIN_FEATURE_PIXELS: f64 = 2.0;
+
+    /// the ways of one zoom band, simplified; ways too small to see are missing
+    pub struct Level {
+        pub zoom: f64,
+        pub ways: HashMap<i64, Vec<LatLon>>,
+    }
+
+    /// a way's coordinates, also in world coordinates, while the levels are built
+    struct SourceWay {
+        id: i64,
+        points: Vec<LatLon>,
+        projected: Vec<(f64, f64)>,
+        closed: bool,
+        bounds: Option<BBox>,
+    }
+
+    /// precomputed simplified geometry for drawing a dataset at low zoom
+    pub struct LevelOfDetail {
+        pub levels: Vec<Level>,
+        /// bounds of every way with something to draw, for skipping the ones out of view
+        pub way_bounds: Vec<(i64, BBox)>,
+    }
+
+    impl LevelOfDetail {
+        pub fn build(dataset: &OsmDataset) -> LevelOfDetail {
+            let projection = Projection::WebMercator;
+            let ways: Vec<SourceWay> = dataset.ways().values()
+                .map(|way| {
+                    let points = dataset.way_coordinates(way);
+                    let projected = points.iter().map(|point| projection.to_world(*point)).collect();
+                    let bounds = BBox::from_points(points.iter().chain(&way.center));
+                    SourceWay { id: way.id, points, projected, closed: way.is_closed(), bounds }
+                })
+                .collect();
+
+            let levels = LEVEL_ZOOMS.iter().enumerate()
+                .map(|(index, zoom)| {
+                    let world_size = TILE_SIZE * 2f64.powf(band_end(index));
+                    let tolerance = TOLERANCE_PIXELS / world_size;
+                    let min_size = MIN_FEATURE_PIXELS / world_size;
+
+                    let simplified = ways.iter()
+                        .filter(|way| way.projected.is_empty() || extent(&way.projected) >= min_size)
+                        .map(|way| {
+                            let kept = simplify(&way.projected, tolerance);
+                            if way.closed && kept.len() < 4 {
+                                return (way.id, way.points.clone());
+                            }
+                            (way.id, kept.iter().map(|index| way.points[*index]).collect())
+                        })
+                        .collect();
+
+                    Level { zoom: *zoom, ways: simplified }
+                })
+                .collect();
+
+            let way_bounds = ways.iter().filter_map(|way| Some((way.id, way.bounds?))).collect();
+            LevelOfDetail { levels, way_bounds }
+        }
+
+        /// the level to draw at `zoom`, `None` when ways should be drawn in full
+        pub fn level(&self, zoom: f64) -> Option<&Level> {
+            match self.levels.iter().position(|level| level.zoom > zoom) {
+                Some(0) => self.levels.first(),
+                Some(index) => self.levels.get(index - 1),
+                None if zoom < band_end(LEVEL_ZOOMS.len() - 1) => self.levels.last(),
+                None => None,
+            }
+        }
+    }
+
+    /// where the next level, or full detail, takes over from level `index`
+    fn band_end(index: usize) -> f64 {
+        LEVEL_ZOOMS.get(index + 1).copied().unwrap_or(LEVEL_ZOOMS[index] + 2.0)
+    }
+
+    /// the larger side of the bounds of `points`
+    fn extent(points: &[(f64, f64)]) -> f64 {
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+        for (x, y) in points {
+            min_x = min_x.min(*x);
+            min_y = min_y.min(*y);
+            max_x = max_x.max(*x);
+            max_y = max_y.max(*y);
+        }
+
+        (max_x - min_x).max(max_y - min_y)
+    }
+
+    /// douglas-peucker, returns the indices of the points to keep, first and last always among them
+    pub fn simplify(points: &[(f64, f64)], tolerance: f64) -> Vec<usize> {
+        if points.len() < 3 {
+            return (0..points.len()).collect();
+        }
+
+        let mut keep = vec![false; points.len()];
+        keep[0] = true;
+        keep[points.len() - 1] = true;
+        let mut stack = vec![(0, points.len() - 1)];
+
+        while let Some((start, end)) = stack.pop() {
+            let farthest = (start + 1..end)
+                .map(|index| (index, segment_distance(points[index], points[start], points[end])))
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+
+            if let Some((index, distance)) = farthest {
+                if distance > tolerance {
+                    keep[index] = true;
+                    stack.push((start, index));
+                    stack.push((index, end));
+                }
+            }
+        }
+
+        keep.iter().enumerate().filter(|(_, keep)| **keep).map(|(index, _)| index).collect()
+    }
+
+    /// distance from `point` to segment `a`-`b`, which may be a single point for closed rings
+    fn segment_distance(point: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let length = dx * dx + dy * dy;
+        let t = if length > 0.0 { (((point.0 - a.0) * dx + (point.1 - a.1) * dy) / length).clamp(0.0, 1.0) } else { 0.0 };
+
+        (point.0 - a.0 - t * dx).hypot(point.1 - a.1 - t * dy)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn keeps_short_lines_whole() {
+            assert_eq!(simplify(&[], 1.0), Vec::<usize>::new());
+            assert_eq!(simplify(&[(0.0, 0.0)], 1.0), vec![0]);
+            assert_eq!(simplify(&[(0.0, 0.0), (5.0, 5.0)], 1.0), vec![0, 1]);
+            assert_eq!(simplify(&[(0.0, 0.0), (0.0, 0.0)], 1.0), vec![0, 1]);
+        }
+
+        #[test]
+        fn drops_collinear_points() {
+            let line = [(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (5.0, 5.0), (10.0, 10.0)];
+            assert_eq!(simplify(&line, 0.0), vec![0, 4]);
+        }
+
+        #[test]
+        fn keeps_points_beyond_the_tolerance_only() {
+            let line = [(0.0, 0.0), (5.0, 0.5), (10.0, 0.0), (15.0, 4.0), (20.0, 0.0)];
+            assert_eq!(simplify(&line, 1.0), vec![0, 2, 3, 4]);
+            assert_eq!(simplify(&line, 0.5), vec![0, 2, 3, 4]);
+            assert_eq!(simplify(&line, 0.4), vec![0, 1, 2, 3, 4]);
+            assert_eq!(simplify(&line, 5.0), vec![0, 4]);
+        }
+
+        #[test]
+        fn keeps_the_corners_of_closed_rings() {
+            let square = [(0.0, 0.0), (10.0, 0.0), (10.0, 0.1), (10.0, 10.0), (0.0, 10.0), (0.0, 0.0)];
+            assert_eq!(simplify(&square, 1.0), vec![0, 1, 3, 4, 5]);
+        }
+    }
+}
+
+pub mod map_layer {
+    use std::collections::HashMap;
+    use std::error::Error;
+
+    use sdl2::pixels::{Color, PixelFormatEnum};
+    use sdl2::rect::Rect;
+    use sdl2::render::{BlendMode, Canvas, RenderTarget, Texture, TextureCreator};
+
+    use crate::camera::Camera;
+    use crate::dataset::OsmDataset;
+    use crate::lod::LevelOfDetail;
+    use crate::map::draw_map;
+    use crate::style::StyleSheet;
+
+    /// the map is cached in squares this many pixels across
+    pub const LAYER_TILE_SIZE: u32 = 256;
+    /// tiles kept around before the least recently drawn ones are dropped
+    pub const LAYER_CAPACITY: usize = 128;
+
+    struct CachedTile<'a> {
+        texture: Texture<'a>,
+        last_drawn: u64,
+    }
+
+    /// the static map drawn once into render target textures and copied to the screen every frame
+    ///
+    /// tiles sit on a pixel grid fixed to the world at the current zoom, so panning only draws the tiles that come into view;
+    /// a zoom change starts over, data or style changes need `invalidate`
+    pub struct MapLayer<'a> {
+        tiles: HashMap<(i64, i64), CachedTile<'a>>,
+        zoom: f64,
+        frame: u64,
+    }
+
+    impl<'a> Default for MapLayer<'a> {
+        fn default() -> MapLayer<'a> {
+            MapLayer { tiles: HashMap::new(), zoom: f64::NAN, frame: 0 }
+        }
+    }
+
+    impl<'a> MapLayer<'a> {
+        pub fn invalidate(&mut self) {
+            self.tiles.clear();
+        }
+
+        pub fn draw<T: RenderTarget, C>(
+            &mut self,
+            canvas: &mut Canvas<T>,
+            texture_creator: &'a TextureCreator<C>,
+            camera: &Camera,
+            dataset: &OsmDataset,
+            lod: &LevelOfDetail,
+            style_sheet: &StyleSheet,
+        ) -> Result<(), Box<dyn Error>> {
+            if camera.zoom != self.zoom {
+                self.invalidate();
+                self.zoom = camera.zoom;
+            }
+            self.frame += 1;
+
+            let size = LAYER_TILE_SIZE as f64;
+            let world_size = camera.world_size();
+            let (center_x, center_y) = camera.projection.to_world(camera.center);
+            let left = center_x * world_size - camera.viewport_width as f64 / 2.0;
+            let top = center_y * world_size - camera.viewport_height as f64 / 2.0;
+            let columns = (left / size).floor() as i64..=((left + camera.viewport_width as f64) / size).floor() as i64;
+            let rows = (top / size).floor() as i64..=((top + camera.viewport_height as f64) / size).floor() as i64;
+
+            for row in rows {
+                for column in columns.clone() {
+                    if !self.tiles.contains_key(&(column, row)) {
+                        let texture = render_tile(canvas, texture_creator, camera, (column, row), dataset, lod, style_sheet)?;
+                        self.tiles.insert((column, row), CachedTile { texture, last_drawn: self.frame });
+                    }
+
+                    let cached = self.tiles.get_mut(&(column, row)).unwrap();
+                    cached.last_drawn = self.frame;
+                    let x = (column as f64 * size - left).round() as i32;
+                    let y = (row as f64 * size - top).round() as i32;
+                    canvas.copy(&cached.texture, None, Rect::new(x, y, LAYER_TILE_SIZE, LAYER_TILE_SIZE))?;
+                }
+            }
+
+            while self.tiles.len() > LAYER_CAPACITY {
+                let oldest = self.tiles.iter().min_by_key(|(_, cached)| cached.last_drawn).map(|(tile, _)| *tile);
+                if let Some(tile) = oldest {
+                    self.tiles.remove(&tile);
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    /// draws the map under tile `(column, row)` into a transparent texture
+    fn render_tile<'a, T: RenderTarget, C>(
+        canvas: &mut Canvas<T>,
+        texture_creator: &'a TextureCreator<C>,
+        camera: &Camera,
+        (column, row): (i64, i64),
+        dataset: &OsmDataset,
+        lod: &LevelOfDetail,
+        style_sheet: &StyleSheet,
+    ) -> Result<Texture<'a>, Box<dyn Error>> {
+        let size = LAYER_TILE_SIZE as f64;
+        let world_size = camera.world_size();
+        let center = camera.projection.from_world((column as f64 + 0.5) * size / world_size, (row as f64 + 0.5) * size / world_size);
+        let tile_camera = Camera::new(center, camera.zoom, LAYER_TILE_SIZE, LAYER_TILE_SIZE);
+
+        let mut texture = texture_creator.create_texture_target(PixelFormatEnum::RGBA8888, LAYER_TILE_SIZE, LAYER_TILE_SIZE)?;
+        texture.set_blend_mode(BlendMode::Blend);
+        canvas.with_texture_canvas(&mut texture, |tile_canvas| {
+            tile_canvas.set_blend_mode(BlendMode::None);
+            tile_canvas.set_draw_color(Color::RGBA(0, 0, 0, 0));
+            tile_canvas.clear();
+            tile_canvas.set_blend_mode(BlendMode::Blend);
+            draw_map(tile_canvas, &tile_camera, dataset, lod, style_sheet);
+        })?;
+
+        Ok(texture)
+    }
+}
+
+pub mod timing {
+    /// frames longer than this are cut short so a stall does not turn into a burst of catch up updates, in seconds
+    pub const MAX_FRAME_TIME: f64 = 0.25;
+
+    /// runs updates at a fixed rate however fast frames are drawn
+    ///
+    /// `begin_frame` adds the time that passed, `step` hands it out in ticks of `dt`,
+    /// and `alpha` is how far into the next tick the frame is, for interpolated drawing
+    pub struct FixedTimestep {
+        dt: f64,
+        accumulator: f64,
+        last_ticks: u64,
+    }
+
+    impl FixedTimestep {
+        /// `tick_rate` updates per second, starting at `ticks` milliseconds
+        pub fn new(tick_rate: f64, ticks: u64) -> FixedTimestep {
+            FixedTimestep { dt: 1.0 / tick_rate.max(1.0), accumulator: 0.0, last_ticks: ticks }
+        }
+
+        pub fn begin_frame(&mut self, ticks: u64) {
+            let elapsed = ticks.saturating_sub(self.last_ticks) as f64 / 1000.0;
+            self.last_ticks = ticks;
+            self.accumulator += elapsed.min(MAX_FRAME_TIME);
+        }
+
+        /// `true` while there is a whole tick left to run
+        pub fn step(&mut self) -> bool {
+            if self.accumulator >= self.dt {
+                self.accumulator -= self.dt;
+                true
+            } else {
+                false
+            }
+        }
+
+        /// seconds per update
+        pub fn dt(&self) -> f64 {
+            self.dt
+        }
+
+        pub fn alpha(&self) -> f64 {
+            self.accumulator / self.dt
+        }
+    }
+
+    /// slowest and fastest the game clock can run
+    pub const MIN_SCALE: f64 = 0.125;
+    pub const MAX_SCALE: f64 = 8.0;
+
+    /// game time in milliseconds, owned by the engine and only moving while it is advanced
+    ///
+    /// unlike sdl ticks it stops while paused and runs slower or faster with `scale`, so timers built on it do too
+    pub struct GameClock {
+        now: u64,
+        /// fraction of a millisecond carried over between advances
+        remainder: f64,
+        scale: f64,
+        paused: bool,
+    }
+
+    impl Default for GameClock {
+        fn default() -> GameClock {
+            GameClock { now: 0, remainder: 0.0, scale: 1.0, paused: false }
+        }
+    }
+
+    impl GameClock {
+        /// milliseconds of game time so far
+        pub fn now(&self) -> u64 {
+            self.now
+        }
+
+        /// moves the clock on by `seconds` of real time and returns how many game seconds that was
+        pub fn advance(&mut self, seconds: f64) -> f64 {
+            if self.paused { return 0.0; }
+
+            let game_seconds = seconds * self.scale;
+            let milliseconds = game_seconds * 1000.0 + self.remainder;
+            self.now += milliseconds.floor() as u64;
+            self.remainder = milliseconds.fract();
+            game_seconds
+        }
+
+        pub fn pause(&mut self) {
+            self.paused = true;
+        }
+
+        pub fn resume(&mut self) {
+            self.paused = false;
+        }
+
+        pub fn toggle_pause(&mut self) {
+            self.paused = !self.paused;
+        }
+
+        pub fn paused(&self) -> bool {
+            self.paused
+        }
+
+        pub fn scale(&self) -> f64 {
+            self.scale
+        }
+
+        /// below 1 is slow motion, above fast forward
+        pub fn set_scale(&mut self, scale: f64) {
+            self.scale = scale.clamp(MIN_SCALE, MAX_SCALE);
+        }
+    }
+}
+
+pub mod ecs {
+    use std::collections::HashMap;
+    use std::hash::Hash;
+
+    use sdl2::pixels::Color;
+    use sdl2::rect::Rect;
+
+    use crate::camera::Camera;
+    use crate::geo::LatLon;
+    use crate::sprite::Direction;
+
+    /// a slot in the component storages plus the generation it was handed out in,
+    /// so a handle kept past `despawn` never matches whatever reuses the slot
+    #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+    pub struct Entity {
+        pub index: u32,
+        pub generation: u32,
+    }
+
+    impl Entity {
+        /// unique for the whole run, for things that key by plain ids
+        pub fn to_bits(self) -> u64 {
+            (self.generation as u64) << 32 | self.index as u64
+        }
+    }
+
+    /// hands out entities, reusing the slots of despawned ones under a new generation
+    #[derive(Default)]
+    pub struct Entities {
+        generations: Vec<u32>,
+        alive: Vec<bool>,
+        free: Vec<u32>,
+    }
+
+    impl Entities {
+        pub fn create(&mut self) -> Entity {
+            match self.free.pop() {
+                Some(index) => {
+                    self.alive[index as usize] = true;
+                    Entity { index, generation: self.generations[index as usize] }
+                }
+                None => {
+                    self.generations.push(0);
+                    self.alive.push(true);
+                    Entity { index: self.generations.len() as u32 - 1, generation: 0 }
+                }
+            }
+        }
+
+        /// `false` when the entity was already gone
+        pub fn destroy(&mut self, entity: Entity) -> bool {
+            if !self.is_alive(entity) { return false; }
+
+            let index = entity.index as usize;
+            self.alive[index] = false;
+            self.generations[index] += 1;
+            self.free.push(entity.index);
+            true
+        }
+
+        pub fn is_alive(&self, entity: Entity) -> bool {
+            let index = entity.index as usize;
+            self.alive.get(index) == Some(&true) && self.generations[index] == entity.generation
+        }
+
+        pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+            self.generations.iter().zip(&self.alive).enumerate()
+                .filter(|(_, (_, alive))| **alive)
+                .map(|(index, (generation, _))| Entity { index: index as u32, generation: *generation })
+        }
+    }
+
+    /// one component type for every entity that has it, indexed by entity slot
+    pub struct Storage<T> {
+        slots: Vec<Option<(u32, T)>>,
+    }
+
+    impl<T> Default for Storage<T> {
+        fn default() -> Storage<T> {
+            Storage { slots: Vec::new() }
+        }
+    }
+
+    impl<T> Storage<T> {
+        /// adds or replaces the component of `entity`
+        pub fn insert(&mut self, entity: Entity, component: T) {
+            let index = entity.index as usize;
+            if self.slots.len() <= index {
+                self.slots.resize_with(index + 1, || None);
+            }
+            self.slots[index] = Some((entity.generation, component));
+        }
+
+        pub fn remove(&mut self, entity: Entity) -> Option<T> {
+            match self.slots.get(entity.index as usize) {
+                Some(Some((generation, _))) if *generation == entity.generation => self.slots[entity.index as usize].take().map(|(_, component)| component),
+                _ => None,
+            }
+        }
+
+        pub fn get(&self, entity: Entity) -> Option<&T> {
+            match self.slots.get(entity.index as usize) {
+                Some(Some((generation, component))) if *generation == entity.generation => Some(component),
+                _ => None,
+            }
+        }
+
+        pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+            match self.slots.get_mut(entity.index as usize) {
+                Some(Some((generation, component))) if *generation == entity.generation => Some(component),
+                _ => None,
+            }
+        }
+
+        pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+            self.slots.iter().enumerate().filter_map(|(index, slot)| {
+                slot.as_ref().map(|(generation, component)| (Entity { index: index as u32, generation: *generation }, component))
+            })
+        }
+
+        pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+            self.slots.iter_mut().enumerate().filter_map(|(index, slot)| {
+                slot.as_mut().map(|(generation, component)| (Entity { index: index as u32, generation: *generation }, component))
+            })
+        }
+    }
+
+    /// where an entity is on the map, and where it was before the last update for interpolated drawing
+    #[derive(Clone, Copy, Debug)]
+    pub struct Transform {
+        pub location: LatLon,
+        pub previous_location: LatLon,
+    }
+
+    impl Transform {
+        pub fn new(location: LatLon) -> Transform {
+            Transform { location, previous_location: location }
+        }
+
+        /// moves by screen pixels at the camera's zoom, fractions of a pixel included
+        pub fn move_by(&mut self, dx: f64, dy: f64, camera: &Camera) {
+            let world_size = camera.world_size();
+            let (x, y) = camera.projection.to_world(self.location);
+            self.location = camera.projection.from_world(x + dx / world_size, y + dy / world_size);
+        }
+
+        /// `alpha` of the way from the previous location to the current one
+        pub fn interpolated(&self, alpha: f64) -> LatLon {
+            LatLon::new(
+                self.previous_location.lat + (self.location.lat - self.previous_location.lat) * alpha,
+                self.previous_location.lon + (self.location.lon - self.previous_location.lon) * alpha,
+            )
+        }
+    }
+
+    /// drawn as a filled square `size` pixels across
+    pub struct Render {
+        pub color: Color,
+        pub size: i32,
+    }
+
+    pub enum Ai {
+        /// walks in a random direction until game clock time `until`, then picks another
+        Wander { direction: Direction, until: u64 },
+    }
+
+    /// a square `size` pixels across around the entity, for clicks and touching
+    pub struct Collider {
+        pub size: i32,
+    }
+
+    impl Collider {
+        pub fn rect(&self, (x, y): (i32, i32)) -> Rect {
+            Rect::new(x - self.size / 2, y - self.size / 2, self.size as u32, self.size as u32)
+        }
+    }
+
+    pub struct Health {
+        pub current: i32,
+        pub max: i32,
+    }
+
+    impl Health {
+        pub fn new(max: i32) -> Health {
+            Health { current: max, max }
+        }
+    }
+
+    /// moved with the arrow keys
+    pub struct PlayerControl;
+
+    /// picked up when a player touches it
+    pub struct Pickup {
+        pub value: u32,
+    }
+
+    /// moved along the gpx playback
+    pub struct FollowTrack;
+
+    /// every entity and component; new kinds of entities are new combinations of these
+    #[derive(Default)]
+    pub struct World {
+        pub entities: Entities,
+        pub transforms: Storage<Transform>,
+        pub renders: Storage<Render>,
+        pub ais: Storage<Ai>,
+        pub colliders: Storage<Collider>,
+        pub healths: Storage<Health>,
+        pub players: Storage<PlayerControl>,
+        pub pickups: Storage<Pickup>,
+        pub track_followers: Storage<FollowTrack>,
+    }
+
+    impl World {
+        pub fn spawn(&mut self) -> Entity {
+            self.entities.create()
+        }
+
+        /// removes the entity with all its components
+        pub fn despawn(&mut self, entity: Entity) {
+            if !self.entities.destroy(entity) { return; }
+
+            self.transforms.remove(entity);
+            self.renders.remove(entity);
+            self.ais.remove(entity);
+            self.colliders.remove(entity);
+            self.healths.remove(entity);
+            self.players.remove(entity);
+            self.pickups.remove(entity);
+            self.track_followers.remove(entity);
+        }
+    }
+
+    /// adds the components that make a freshly spawned entity what it is
+    pub type Prefab = fn(&mut World, Entity);
+
+    /// prefabs by kind, so spawning a kind is a lookup instead of a list of every kind
+    pub struct Prefabs<K> {
+        prefabs: HashMap<K, Prefab>,
+    }
+
+    impl<K> Default for Prefabs<K> {
+        fn default() -> Prefabs<K> {
+            Prefabs { prefabs: HashMap::new() }
+        }
+    }
+
+    impl<K: Eq + Hash> Prefabs<K> {
+        pub fn with(mut self, kind: K, prefab: Prefab) -> Prefabs<K> {
+            self.prefabs.insert(kind, prefab);
+            self
+        }
+
+        /// a new entity at `location` built by the prefab of `kind`, `None` when no prefab is registered for it
+        pub fn spawn(&self, world: &mut World, kind: K, location: LatLon) -> Option<Entity> {
+            let prefab = self.prefabs.get(&kind)?;
+            let entity = world.spawn();
+            world.transforms.insert(entity, Transform::new(location));
+            prefab(world, entity);
+            Some(entity)
+        }
+    }
+
+    pub type System<C> = fn(&mut World, &mut C);
+
+    /// systems run one after another in the order they were added, all sharing a context
+    pub struct Schedule<C> {
+        systems: Vec<System<C>>,
+    }
+
+    impl<C> Default for Schedule<C> {
+        fn default() -> Schedule<C> {
+            Schedule { systems: Vec::new() }
+        }
+    }
+
+    impl<C> Schedule<C> {
+        pub fn with(mut self, system: System<C>) -> Schedule<C> {
+            self.systems.push(system);
+            self
+        }
+
+        pub fn run(&self, world: &mut World, context: &mut C) {
+            for system in &self.systems {
+                system(world, context);
+            }
+        }
+    }
+}